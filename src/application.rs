@@ -2,36 +2,55 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    cell::{OnceCell, RefCell},
+    cell::{Cell, OnceCell, RefCell},
+    collections::HashMap,
+    path::PathBuf,
     rc::Rc,
     sync::{Arc, OnceLock},
 };
 
 use adw::{gio, glib, prelude::*, subclass::prelude::*};
-use eyre::WrapErr;
 use gettextrs::gettext;
+use mediathekviewweb::Mediathek;
 use smart_default::SmartDefault;
 
 use crate::{
+    cli,
     config::{APP_ID, PROFILE, VERSION},
+    deep_link::DeepLink,
+    error::ApiError,
+    hls, http, iptv,
     launcher::{ExternalProgram, ExternalProgramType, ProgramSelector},
+    network,
     player::{TvPlayer, VideoInfo},
     preferences::TvPreferencesDialog,
-    settings::{TvSettings, VideoQuality},
+    remote_control, search_provider,
+    settings::TvSettings,
     utils::{show_error, spawn, spawn_clone, tokio, AsyncResource},
     window::TvWindow,
-    zapp::Zapp,
+    zapp::{ChannelId, ChannelInfo, Show, ShowsResult, Zapp},
 };
 
+/// How long the application keeps running after a D-Bus call if it was started as a service.
+const SERVICE_INACTIVITY_TIMEOUT: u32 = 60_000;
+
 mod imp {
 
     use super::*;
 
     #[derive(Debug, SmartDefault)]
     pub struct TvApplication {
-        #[default(Arc::new(Zapp::new().expect("failed to initialize Zapp client")))]
         pub(super) zapp: Arc<Zapp>,
+        /// Recreated when the proxy changes to use the new HTTP client.
+        #[default(RefCell::new(new_mediathek()))]
+        pub(super) mediathek: RefCell<Arc<Mediathek>>,
         pub(super) live_channels: AsyncResource<Rc<crate::zapp::ChannelInfoList>>,
+        /// Set if the last attempt to load the live channels failed.
+        pub(super) live_channels_error: RefCell<Option<ApiError>>,
+        pub(super) shows: RefCell<HashMap<ChannelId, Vec<Show>>>,
+        pub(super) iptv_server: RefCell<Option<gio::ApplicationHoldGuard>>,
+        /// Set if the application was started without a window, e.g. to only run the IPTV server.
+        pub(super) skip_activate: Cell<bool>,
         pub(super) window: RefCell<Option<glib::WeakRef<TvWindow>>>,
         pub(super) player: RefCell<Option<glib::WeakRef<TvPlayer>>>,
     }
@@ -48,13 +67,50 @@ mod imp {
             self.parent_constructed();
             let obj = self.obj();
             obj.setup_gactions();
+            obj.setup_command_line_options();
         }
     }
 
     impl ApplicationImpl for TvApplication {
+        fn startup(&self) {
+            self.parent_startup();
+
+            // started by D-Bus activation of the search provider or remote control
+            let obj = self.obj();
+            if obj.flags().contains(gio::ApplicationFlags::IS_SERVICE) {
+                obj.set_inactivity_timeout(SERVICE_INACTIVITY_TIMEOUT);
+            }
+
+            http::connect_proxy_settings_changed(|| {
+                spawn(async { TvApplication::get().update_proxy().await })
+            });
+
+            spawn(async {
+                if let Err(e) = search_provider::register().await {
+                    tracing::warn!("{e:?}");
+                }
+                if let Err(e) = remote_control::register().await {
+                    tracing::warn!("{e:?}");
+                }
+            });
+        }
         fn activate(&self) {
+            if self.skip_activate.replace(false) {
+                return;
+            }
             self.obj().window().present();
         }
+        fn open(&self, files: &[gio::File], _hint: &str) {
+            for file in files {
+                self.obj().open_uri(&file.uri());
+            }
+        }
+        fn handle_local_options(&self, options: &glib::VariantDict) -> glib::ExitCode {
+            match self.obj().run_command_line_options(options) {
+                Some(exit_code) => exit_code,
+                None => self.parent_handle_local_options(options),
+            }
+        }
     }
 
     impl GtkApplicationImpl for TvApplication {}
@@ -75,7 +131,7 @@ impl TvApplication {
     pub fn new() -> Self {
         let slf: Self = glib::Object::builder()
             .property("application-id", APP_ID)
-            .property("flags", gio::ApplicationFlags::FLAGS_NONE)
+            .property("flags", gio::ApplicationFlags::HANDLES_OPEN)
             .property("resource-base-path", "/de/k_bo/televido")
             .build();
 
@@ -90,23 +146,38 @@ impl TvApplication {
             move || {
                 let zapp = zapp.clone();
                 Box::pin(async move {
-                    match tokio(async move {
-                        zapp.channel_info_list()
-                            .await
-                            .wrap_err("Failed to load channel info list")
-                    })
-                    .await
-                    {
-                        Ok(channels) => Rc::new(channels),
-                        Err(e) => {
-                            show_error(e.wrap_err(gettext("Failed to load livestream channels")));
-                            Default::default()
-                        }
-                    }
+                    // errors are shown by the live view, which offers to retry
+                    let (mut channels, error) =
+                        match tokio(async move { zapp.channel_info_list().await }).await {
+                            Ok(channels) => (channels, None),
+                            Err(e) => {
+                                tracing::error!("failed to load channel info list: {e:?}");
+                                (Default::default(), Some(e))
+                            }
+                        };
+                    TvApplication::get()
+                        .imp()
+                        .live_channels_error
+                        .replace(error);
+                    channels.extend(iptv::custom_channel_infos());
+
+                    Rc::new(channels)
                 })
             }
         });
-        spawn(async move { live_channels.load() });
+        TvSettings::get().connect_custom_channels_changed(glib::clone!(
+            #[strong]
+            live_channels,
+            move |_| live_channels.load()
+        ));
+        spawn(glib::clone!(
+            #[strong]
+            slf,
+            async move {
+                slf.update_proxy().await;
+                live_channels.load()
+            }
+        ));
 
         slf
     }
@@ -153,20 +224,213 @@ impl TvApplication {
             Some(player) => player,
             None => {
                 let new_player = TvPlayer::new(self);
+                new_player.connect_close_request(|_| {
+                    remote_control::emit_playback_stopped();
+                    glib::Propagation::Proceed
+                });
                 *player = Some(glib::clone::Downgrade::downgrade(&new_player));
                 new_player
             }
         }
     }
 
+    /// Closes the internal player if it is open.
+    pub fn stop_playback(&self) {
+        let player = self
+            .imp()
+            .player
+            .borrow()
+            .as_ref()
+            .and_then(|p| p.upgrade());
+        if let Some(player) = player {
+            player.close();
+        }
+    }
+
     pub fn zapp(&self) -> Arc<Zapp> {
         self.imp().zapp.clone()
     }
 
+    pub fn mediathek(&self) -> Arc<Mediathek> {
+        self.imp().mediathek.borrow().clone()
+    }
+
+    /// Applies the proxy from the preferences or the system settings to all HTTP clients.
+    pub async fn update_proxy(&self) {
+        if http::update_proxy().await {
+            self.imp().mediathek.replace(new_mediathek());
+        }
+    }
+
     pub fn live_channels(&self) -> AsyncResource<Rc<crate::zapp::ChannelInfoList>> {
         self.imp().live_channels.clone()
     }
 
+    /// Returns the error that occurred while loading the live channels, if any.
+    pub fn live_channels_error(&self) -> Option<ApiError> {
+        self.imp().live_channels_error.borrow().clone()
+    }
+
+    pub fn cache_shows(&self, channel_id: ChannelId, shows: Vec<Show>) {
+        self.imp().shows.borrow_mut().insert(channel_id, shows);
+    }
+
+    /// Returns the programme of a live channel, using cached data if available.
+    pub async fn shows(&self, channel_id: &ChannelId) -> eyre::Result<Vec<Show>> {
+        let now = time::OffsetDateTime::now_utc();
+
+        // cached data is outdated once all of its shows have ended
+        if let Some(shows) = self
+            .imp()
+            .shows
+            .borrow()
+            .get(channel_id)
+            .filter(|shows| shows.iter().any(|show| show.end_time > now))
+        {
+            return Ok(shows.clone());
+        }
+        if iptv::is_custom_channel(channel_id) {
+            return Ok(Vec::new());
+        }
+
+        let zapp = self.zapp();
+        let shows = match tokio({
+            let channel_id = channel_id.clone();
+            async move { zapp.shows(&channel_id).await }
+        })
+        .await?
+        {
+            ShowsResult::Shows(shows) => shows,
+            ShowsResult::Error(_) => Vec::new(),
+        };
+        self.cache_shows(channel_id.clone(), shows.clone());
+
+        Ok(shows)
+    }
+
+    /// Returns the show that is currently running on a live channel.
+    pub async fn current_show(&self, channel_id: &ChannelId) -> eyre::Result<Option<Show>> {
+        let now = time::OffsetDateTime::now_utc();
+
+        Ok(self
+            .shows(channel_id)
+            .await?
+            .into_iter()
+            .find(|show| show.start_time <= now && now < show.end_time))
+    }
+
+    /// Returns the show that is currently running on a live channel without loading the programme.
+    pub fn cached_current_show(&self, channel_id: &ChannelId) -> Option<Show> {
+        let now = time::OffsetDateTime::now_utc();
+
+        self.imp()
+            .shows
+            .borrow()
+            .get(channel_id)?
+            .iter()
+            .find(|show| show.start_time <= now && now < show.end_time)
+            .cloned()
+    }
+
+    /// Returns the live channels selected by the user in their preferred order.
+    pub async fn visible_live_channels(&self) -> Vec<(ChannelId, ChannelInfo)> {
+        let visible_channels = TvSettings::get().live_channels();
+        let live_channels = self.live_channels().await;
+
+        if visible_channels.is_empty() {
+            live_channels
+                .iter()
+                .map(|(channel_id, channel_info)| (channel_id.clone(), channel_info.clone()))
+                .collect()
+        } else {
+            visible_channels
+                .into_iter()
+                .filter_map(|channel_id| {
+                    let channel_info = live_channels.get(&channel_id)?;
+                    Some((channel_id, channel_info.clone()))
+                })
+                .collect()
+        }
+    }
+
+    /// Starts the IPTV server and keeps the application running until it is quit.
+    pub fn start_iptv_server(&self) {
+        if self.imp().iptv_server.borrow().is_some() {
+            return;
+        }
+        self.imp().iptv_server.replace(Some(self.hold()));
+
+        let settings = TvSettings::get();
+        let port = u16::try_from(settings.iptv_server_port()).expect("port should be in range");
+        let proxy_streams = settings.iptv_server_proxy_streams();
+        let local_network = settings.iptv_server_local_network();
+
+        let slf = self.clone();
+        spawn(async move {
+            if let Err(e) = tokio(iptv::server::serve(port, proxy_streams, local_network)).await {
+                show_error(e.wrap_err(gettext("Failed to run the IPTV server")));
+            }
+            slf.imp().iptv_server.take();
+        });
+    }
+
+    /// Opens a `televido://` link.
+    pub fn open_uri(&self, uri: &str) {
+        match DeepLink::parse(uri) {
+            Ok(DeepLink::Live { channel_id }) => {
+                let slf = self.clone();
+                spawn(async move { slf.play_live(&channel_id).await })
+            }
+            Ok(DeepLink::Search { query_string }) => {
+                let window = self.window();
+                window.search_mediathek(&query_string);
+                window.present();
+            }
+            Ok(DeepLink::Play(video)) => {
+                let slf = self.clone();
+                spawn(async move { slf.play(video).await })
+            }
+            Err(e) => {
+                self.window().present();
+                show_error(e);
+            }
+        }
+    }
+
+    /// Finds a live channel by its ID or name.
+    pub async fn find_live_channel(&self, channel: &str) -> Option<(ChannelId, ChannelInfo)> {
+        let live_channels = self.live_channels().await;
+
+        live_channels
+            .iter()
+            .find(|(id, _)| id.as_ref() == channel)
+            .or_else(|| {
+                live_channels.iter().find(|(id, info)| {
+                    id.as_ref().eq_ignore_ascii_case(channel)
+                        || info.name.eq_ignore_ascii_case(channel)
+                })
+            })
+            .map(|(id, info)| (id.clone(), info.clone()))
+    }
+
+    /// Plays a live channel identified by its ID or name.
+    pub async fn play_live(&self, channel: &str) {
+        let Some((channel_id, channel_info)) = self.find_live_channel(channel).await else {
+            show_error(eyre::Report::msg(
+                // translators: `{}` is replaced by the channel name, e.g. `ZDF`
+                gettext("Unknown live channel “{}”").replace("{}", channel),
+            ));
+            return;
+        };
+
+        self.play(VideoInfo::Live {
+            title: channel_info.name,
+            uri: channel_info.stream_url,
+            channel_id: channel_id.to_string(),
+        })
+        .await
+    }
+
     pub async fn play(&self, video: VideoInfo) {
         let settings = TvSettings::get();
 
@@ -203,32 +467,50 @@ impl TvApplication {
                 }
             };
 
-            let uri = match video {
-                VideoInfo::Live { uri, .. } => uri,
-                VideoInfo::Mediathek {
-                    preferred_quality,
-                    uri_high,
-                    uri_medium,
-                    uri_low,
-                    ..
-                } => match preferred_quality {
-                    VideoQuality::High => uri_high.expect("no high quality video URI set"),
-                    VideoQuality::Medium => uri_medium.expect("no medium quality video URI set"),
-                    VideoQuality::Low => uri_low.expect("no low quality video URI set"),
-                },
+            let uri = match &video {
+                VideoInfo::Live {
+                    uri, channel_id, ..
+                } => hls::resolve_live_uri(channel_id, uri.clone()).await,
+                VideoInfo::Mediathek { .. } => video
+                    .uri()
+                    .expect("no video URI set for the preferred quality")
+                    .to_owned(),
             };
             match player.open(uri).await {
-                Ok(()) => (),
+                Ok(()) => remote_control::emit_playback_started(&video),
                 Err(e) => show_error(e.wrap_err(gettext("Failed to play video stream"))),
             }
         } else {
             let player = self.player();
+            if matches!(video, VideoInfo::Live { .. }) {
+                player.set_live_channels(self.visible_live_channels().await);
+            }
+            remote_control::emit_playback_started(&video);
             player.play(video);
             player.present();
         }
     }
 
     pub async fn download(&self, uri: String) {
+        if network::bandwidth_saver() {
+            let dialog = adw::AlertDialog::new(
+                Some(&gettext("Download on a Metered Connection?")),
+                Some(&gettext(
+                    "Videos can be several gigabytes in size, which might exceed your data plan.",
+                )),
+            );
+            dialog.add_responses(&[
+                ("cancel", &gettext("_Cancel")),
+                ("download", &gettext("_Download")),
+            ]);
+            dialog.set_default_response(Some("cancel"));
+            dialog.set_close_response("cancel");
+
+            if dialog.choose_future(&self.window()).await != "download" {
+                return;
+            }
+        }
+
         let settings = TvSettings::get();
         let downloader_name = settings.video_downloader_name();
         let downloader_id = settings.video_downloader_id();
@@ -283,6 +565,23 @@ impl TvApplication {
                 TvPreferencesDialog::new().present(Some(&app.window()))
             })
             .build();
+        let play_live_action = gio::ActionEntry::builder("play-live")
+            .parameter_type(Some(glib::VariantTy::STRING))
+            .activate(move |app: &Self, _, variant| {
+                if let Some(channel) = variant.and_then(|v| v.get::<String>()) {
+                    // without a window, the application would quit before the channels are loaded
+                    let hold_guard = app.hold();
+                    let app = app.clone();
+                    spawn(async move {
+                        app.play_live(&channel).await;
+                        drop(hold_guard);
+                    })
+                }
+            })
+            .build();
+        let start_iptv_server_action = gio::ActionEntry::builder("start-iptv-server")
+            .activate(move |app: &Self, _, _| app.start_iptv_server())
+            .build();
         let download_action = gio::ActionEntry::builder("download")
             .parameter_type(Some(glib::VariantTy::STRING))
             .activate(move |app: &Self, _, variant| {
@@ -296,6 +595,8 @@ impl TvApplication {
             quit_action,
             about_action,
             preferences_action,
+            play_live_action,
+            start_iptv_server_action,
             download_action,
         ]);
 
@@ -306,6 +607,113 @@ impl TvApplication {
         self.set_accels_for_action("window.show-mediathek", &["<primary>m"]);
     }
 
+    fn setup_command_line_options(&self) {
+        self.add_main_option(
+            "live",
+            glib::Char::from(0),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::String,
+            &gettext("Play a live channel, e.g. “zdf”"),
+            Some(&gettext("CHANNEL")),
+        );
+        self.add_main_option(
+            "search",
+            glib::Char::from(0),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::String,
+            &gettext("Search the Mediathek and print the results"),
+            Some(&gettext("QUERY")),
+        );
+        self.add_main_option(
+            "list-channels",
+            glib::Char::from(0),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::None,
+            &gettext("Print the available live channels"),
+            None,
+        );
+        self.add_main_option(
+            "json",
+            glib::Char::from(0),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::None,
+            &gettext("Print the output of other options as JSON"),
+            None,
+        );
+        self.add_main_option(
+            "export-m3u",
+            glib::Char::from(0),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::Filename,
+            &gettext("Export the live channels as an M3U playlist"),
+            Some(&gettext("FILE")),
+        );
+        self.add_main_option(
+            "export-xmltv",
+            glib::Char::from(0),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::Filename,
+            &gettext("Export the programme guide of the live channels as XMLTV"),
+            Some(&gettext("FILE")),
+        );
+        self.add_main_option(
+            "iptv-server",
+            glib::Char::from(0),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::None,
+            &gettext("Serve the live channels and their programme guide to other devices"),
+            None,
+        );
+    }
+
+    /// Handles command line options, returns `None` if the application should continue running.
+    fn run_command_line_options(&self, options: &glib::VariantDict) -> Option<glib::ExitCode> {
+        let json = options.contains("json");
+
+        if let Ok(Some(path)) = options.lookup::<PathBuf>("export-m3u") {
+            return Some(cli::run_headless(iptv::export_m3u(path)));
+        }
+        if let Ok(Some(path)) = options.lookup::<PathBuf>("export-xmltv") {
+            return Some(cli::run_headless(iptv::export_xmltv(path)));
+        }
+        if options.contains("list-channels") {
+            return Some(cli::run_headless(cli::list_channels(json)));
+        }
+        if let Ok(Some(query)) = options.lookup::<String>("search") {
+            return Some(cli::run_headless(cli::search(query, json)));
+        }
+        if let Ok(Some(channel)) = options.lookup::<String>("live") {
+            return self.run_in_primary_instance("play-live", Some(&channel.to_variant()));
+        }
+        if options.contains("iptv-server") {
+            return self.run_in_primary_instance("start-iptv-server", None);
+        }
+
+        None
+    }
+
+    /// Activates an action in the running instance or in this one if there is none.
+    fn run_in_primary_instance(
+        &self,
+        action_name: &str,
+        parameter: Option<&glib::Variant>,
+    ) -> Option<glib::ExitCode> {
+        if let Err(e) = self.register(None::<&gio::Cancellable>) {
+            tracing::error!("{e:?}");
+            return Some(glib::ExitCode::FAILURE);
+        }
+
+        self.activate_action(action_name, parameter);
+
+        if self.is_remote() {
+            Some(glib::ExitCode::SUCCESS)
+        } else {
+            // the action takes care of opening a window if necessary
+            self.imp().skip_activate.set(true);
+            None
+        }
+    }
+
     fn show_about(&self) {
         let about = adw::AboutDialog::from_appdata(
             "/de/k_bo/televido/de.k_bo.Televido.metainfo.xml",
@@ -317,3 +725,7 @@ impl TvApplication {
         about.present(Some(&self.window()));
     }
 }
+
+fn new_mediathek() -> Arc<Mediathek> {
+    Arc::new(Mediathek::from(http::client()))
+}
@@ -1,31 +1,71 @@
 // SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use adw::{gdk, gdk::gdk_pixbuf, glib, prelude::*};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use adw::{gdk, gdk::gdk_pixbuf, gio, glib, prelude::*};
 use eyre::WrapErr;
-use phf::phf_map;
 use tracing::error;
 
-use crate::application::TvApplication;
+use crate::{
+    application::TvApplication,
+    channel_registry::{ChannelEntry, ChannelIcon, ChannelRegistry},
+    iptv::{custom_channel, CustomChannel},
+    utils::tokio,
+};
+
+thread_local! {
+    /// Logos that have already been written by [`export_channel_icon`].
+    static EXPORTED_ICONS: RefCell<HashSet<PathBuf>> = Default::default();
+}
 
 pub fn load_channel_icon(channel_id: Option<&str>, image: &gtk::Image, size: i32) {
-    let Some(icon_name) = channel_id.and_then(|id| ICON_NAMES.get(id)) else {
+    let application = TvApplication::get();
+    let scale_factor = application.window().surface().unwrap().scale_factor();
+
+    let Some(channel_id) = channel_id else {
         image.set_icon_name(Some("image-missing-symbolic"));
         return;
     };
 
-    let application = TvApplication::get();
-    let style_manager = application.style_manager();
-    let scale_factor = application.window().surface().unwrap().scale_factor();
-    let size = scale_factor * size;
+    if let Some(channel) = custom_channel(channel_id) {
+        set_custom_icon(&channel, image, size, scale_factor);
+        return;
+    }
+
+    let registry = ChannelRegistry::get();
+    let entry = registry.find(channel_id);
+
+    match entry.and_then(ChannelEntry::icon) {
+        Some(ChannelIcon::Bundled(icon_name)) => {
+            let style_manager = application.style_manager();
+            let size = scale_factor * size;
 
-    set_icon(&style_manager, image, icon_name, size);
+            set_icon(&style_manager, image, &icon_name, size);
 
-    style_manager.connect_dark_notify(glib::clone!(
-        #[weak]
-        image,
-        move |style_manager| set_icon(style_manager, &image, icon_name, size)
-    ));
+            style_manager.connect_dark_notify(glib::clone!(
+                #[weak]
+                image,
+                move |style_manager| set_icon(style_manager, &image, &icon_name, size)
+            ));
+        }
+        Some(ChannelIcon::File(path)) => set_icon_from_file(
+            &path,
+            &registry.display_name(channel_id),
+            image,
+            size,
+            scale_factor,
+        ),
+        None => image.set_paintable(Some(&placeholder_icon(
+            &registry.display_name(channel_id),
+            size,
+            scale_factor,
+        ))),
+    }
 
     fn set_icon(style_manager: &adw::StyleManager, image: &gtk::Image, icon_name: &str, size: i32) {
         match load_icon(
@@ -46,8 +86,7 @@ pub fn load_channel_icon(channel_id: Option<&str>, image: &gtk::Image, size: i32
         size: i32,
         color_scheme: ColorScheme,
     ) -> eyre::Result<gdk::Texture> {
-        let resource =
-            format!("/de/k_bo/televido/icons/scalable/channels/{color_scheme}/{icon_name}");
+        let resource = icon_resource(icon_name, color_scheme);
 
         // load image manually with given size to avoid blurriness caused by scaling after rasterization
         gdk_pixbuf::Pixbuf::from_resource_at_scale(&resource, size, size, true)
@@ -56,80 +95,83 @@ pub fn load_channel_icon(channel_id: Option<&str>, image: &gtk::Image, size: i32
     }
 }
 
-pub fn channel_icon_resource(channel_id: &str) -> Option<String> {
-    let icon_name = ICON_NAMES.get(channel_id)?;
+fn set_custom_icon(channel: &CustomChannel, image: &gtk::Image, size: i32, scale_factor: i32) {
+    if channel.logo.is_empty() {
+        image.set_paintable(Some(&placeholder_icon(&channel.name, size, scale_factor)));
+    } else {
+        set_icon_from_file(
+            Path::new(&channel.logo),
+            &channel.name,
+            image,
+            size,
+            scale_factor,
+        );
+    }
+}
 
-    let application = TvApplication::get();
-    let style_manager = application.style_manager();
-    let color_scheme = ColorScheme::for_style_manager(&style_manager);
+/// Loads a user-provided logo, falling back to a placeholder if it can't be loaded.
+fn set_icon_from_file(path: &Path, name: &str, image: &gtk::Image, size: i32, scale_factor: i32) {
+    match gdk_pixbuf::Pixbuf::from_file_at_scale(
+        path,
+        scale_factor * size,
+        scale_factor * size,
+        true,
+    ) {
+        Ok(pixbuf) => image.set_paintable(Some(&gdk::Texture::for_pixbuf(&pixbuf))),
+        Err(e) => {
+            error!("failed to load channel logo from {}: {e}", path.display());
+            image.set_paintable(Some(&placeholder_icon(name, size, scale_factor)));
+        }
+    }
+}
 
-    Some(format!(
-        "/de/k_bo/televido/icons/scalable/channels/{color_scheme}/{icon_name}"
-    ))
+/// Generates an icon showing the initials of the channel name.
+pub fn placeholder_icon(name: &str, size: i32, scale_factor: i32) -> gdk::Texture {
+    adw::Avatar::new(size, Some(name), true).draw_to_texture(scale_factor)
 }
 
-static ICON_NAMES: phf::Map<&'static str, &'static str> = phf_map! {
-    // live
-    "ard_alpha" => "ard-alpha.svg",
-    "arte" => "arte.svg",
-    "br_nord" => "br.svg",
-    "br_sued" => "br.svg",
-    "das_erste" => "das-erste.svg",
-    "deutsche_welle" => "deutsche-welle.svg",
-    "deutsche_welle_plus" => "deutsche-welle.svg",
-    "dreisat" => "3sat.svg",
-    "hr" => "hr.svg",
-    "kika" => "kika.svg",
-    "mdr_sachsen" => "mdr.svg",
-    "mdr_sachsen_anhalt" => "mdr.svg",
-    "mdr_thueringen" => "mdr.svg",
-    "ndr_hh" => "ndr.svg",
-    "ndr_mv" => "ndr.svg",
-    "ndr_nds" => "ndr.svg",
-    "ndr_sh" => "ndr.svg",
-    "one" => "one.svg",
-    "parlamentsfernsehen_1" => "parlamentsfernsehen.svg",
-    "parlamentsfernsehen_2" => "parlamentsfernsehen.svg",
-    "phoenix" => "phoenix.svg",
-    "rb" => "rb.svg",
-    "rbb_berlin" => "rbb.svg",
-    "rbb_brandenburg" => "rbb.svg",
-    "sr" => "sr.svg",
-    "swr_bw" => "swr.svg",
-    "swr_rp" => "swr.svg",
-    "tagesschau24" => "tagesschau24.svg",
-    "wdr" => "wdr.svg",
-    "zdf" => "zdf.svg",
-    "zdf_info" => "zdf-info.svg",
-    "zdf_neo" => "zdf-neo.svg",
-    // mediathek
-    "3Sat" => "3sat.svg",
-    "ARD" => "ard.svg",
-    "ARTE.DE" => "arte.svg",
-    "ARTE.EN" => "arte.svg",
-    "ARTE.ES" => "arte.svg",
-    "ARTE.FR" => "arte.svg",
-    "ARTE.IT" => "arte.svg",
-    "ARTE.PL" => "arte.svg",
-    "BR" => "br.svg",
-    "DW" => "deutsche-welle.svg",
-    "Funk.net" => "funk.svg",
-    "HR" => "hr.svg",
-    "KiKA" => "kika.svg",
-    "MDR" => "mdr.svg",
-    "NDR" => "ndr.svg",
-    "ORF" => "orf.svg",
-    "PHOENIX" => "phoenix.svg",
-    "Radio Bremen TV" => "rb.svg",
-    "RBB" => "rbb.svg",
-    "rbtv" => "rb.svg",
-    "SR" => "sr.svg",
-    "SRF" => "srf.svg",
-    "SWR" => "swr.svg",
-    "WDR" => "wdr.svg",
-    "ZDF" => "zdf.svg",
-    "ZDF-tivi" => "zdf.svg",
-};
+fn icon_resource(icon_name: &str, color_scheme: ColorScheme) -> String {
+    format!("/de/k_bo/televido/icons/scalable/channels/{color_scheme}/{icon_name}")
+}
+
+/// Writes the channel's logo to `dir` so that it can be used by other programs.
+///
+/// User-provided logos are already stored on disk, so their path is returned directly.
+/// Bundled logos are only written once per run.
+pub async fn export_channel_icon(channel_id: &str, dir: &Path) -> eyre::Result<Option<PathBuf>> {
+    let icon_name = match ChannelRegistry::get()
+        .find(channel_id)
+        .and_then(ChannelEntry::icon)
+    {
+        Some(ChannelIcon::Bundled(icon_name)) => icon_name,
+        Some(ChannelIcon::File(path)) => return Ok(path.exists().then_some(path)),
+        None => return Ok(None),
+    };
+
+    let icon_path = dir.join(&icon_name);
+    if EXPORTED_ICONS.with_borrow(|exported| exported.contains(&icon_path)) {
+        return Ok(Some(icon_path));
+    }
+
+    // other programs usually don't know about our color scheme, so the light variant is used
+    let resource = icon_resource(&icon_name, ColorScheme::Light);
+    let icon_data = gio::resources_lookup_data(&resource, gio::ResourceLookupFlags::NONE)
+        .wrap_err_with(|| format!("failed to load channel logo from {resource}"))?;
+
+    tokio({
+        let icon_path = icon_path.clone();
+        async move {
+            tokio::fs::create_dir_all(icon_path.parent().unwrap()).await?;
+            tokio::fs::write(&icon_path, icon_data).await?;
+            Ok::<(), eyre::Report>(())
+        }
+    })
+    .await
+    .wrap_err_with(|| format!("failed to write channel logo to {}", icon_path.display()))?;
+
+    EXPORTED_ICONS.with_borrow_mut(|exported| exported.insert(icon_path.clone()));
+    Ok(Some(icon_path))
+}
 
 #[derive(Clone, Copy, Debug)]
 enum ColorScheme {
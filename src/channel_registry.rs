@@ -0,0 +1,126 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Metadata of known channels, e.g. their logos and display names.
+//!
+//! The bundled `channels.json` can be extended by a file with the same format in the user's data directory.
+//! Entries are matched by their ID or one of their aliases, so a single entry covers the Zapp IDs
+//! and the MediathekView names of a channel.
+
+use std::{path::PathBuf, sync::OnceLock};
+
+use adw::{gio, glib};
+use eyre::WrapErr;
+use serde::Deserialize;
+
+const BUNDLED_REGISTRY: &str = "/de/k_bo/televido/channels.json";
+
+static REGISTRY: OnceLock<ChannelRegistry> = OnceLock::new();
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChannelEntry {
+    pub id: String,
+    pub name: String,
+    /// File name of a bundled logo or path of a user-provided logo.
+    #[serde(default)]
+    icon: Option<String>,
+    /// The broadcaster the channel belongs to, e.g. `ARD`.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// ISO 3166 code of the country or subdivision the channel is mainly broadcast to.
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(skip)]
+    user_defined: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum ChannelIcon {
+    /// Name of an icon in `icons/scalable/channels/{light,dark}` of the app's resources.
+    Bundled(String),
+    File(PathBuf),
+}
+
+impl ChannelEntry {
+    pub fn icon(&self) -> Option<ChannelIcon> {
+        let icon = self.icon.as_deref().filter(|icon| !icon.is_empty())?;
+
+        Some(if self.user_defined {
+            // relative paths are resolved against the directory of the user's registry
+            ChannelIcon::File(user_registry_dir().join(icon))
+        } else {
+            ChannelIcon::Bundled(icon.to_owned())
+        })
+    }
+    fn matches(&self, channel: &str) -> bool {
+        std::iter::once(&self.id)
+            .chain(&self.aliases)
+            .any(|name| name.eq_ignore_ascii_case(channel))
+    }
+}
+
+#[derive(Debug)]
+pub struct ChannelRegistry {
+    entries: Vec<ChannelEntry>,
+}
+
+impl ChannelRegistry {
+    pub fn get() -> &'static Self {
+        REGISTRY.get_or_init(Self::load)
+    }
+    fn load() -> Self {
+        let mut entries = Vec::new();
+
+        // user-defined entries take precedence over the bundled ones
+        match load_user_entries() {
+            Ok(user_entries) => {
+                entries.extend(user_entries.into_iter().map(|entry| ChannelEntry {
+                    user_defined: true,
+                    ..entry
+                }))
+            }
+            Err(e) => tracing::error!("{e:?}"),
+        }
+        match load_bundled_entries() {
+            Ok(bundled_entries) => entries.extend(bundled_entries),
+            Err(e) => tracing::error!("{e:?}"),
+        }
+
+        Self { entries }
+    }
+    /// Finds the entry for a Zapp channel ID or MediathekView channel name.
+    pub fn find(&self, channel: &str) -> Option<&ChannelEntry> {
+        self.entries.iter().find(|entry| entry.matches(channel))
+    }
+    /// Returns the channel's display name, which falls back to the given ID or name.
+    pub fn display_name(&self, channel: &str) -> String {
+        self.find(channel)
+            .map(|entry| entry.name.clone())
+            .unwrap_or_else(|| channel.to_owned())
+    }
+}
+
+fn user_registry_dir() -> PathBuf {
+    glib::user_data_dir().join("televido")
+}
+
+fn load_bundled_entries() -> eyre::Result<Vec<ChannelEntry>> {
+    let data = gio::resources_lookup_data(BUNDLED_REGISTRY, gio::ResourceLookupFlags::NONE)
+        .wrap_err("failed to load the bundled channel registry")?;
+
+    serde_json::from_slice(&data).wrap_err("failed to parse the bundled channel registry")
+}
+
+fn load_user_entries() -> eyre::Result<Vec<ChannelEntry>> {
+    let path = user_registry_dir().join("channels.json");
+
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).wrap_err_with(|| format!("failed to read {}", path.display())),
+    };
+
+    serde_json::from_slice(&data).wrap_err_with(|| format!("failed to parse {}", path.display()))
+}
@@ -0,0 +1,158 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Headless commands that print their results to stdout and exit.
+
+use std::future::Future;
+
+use adw::glib;
+use eyre::WrapErr;
+use gettextrs::gettext;
+use serde::Serialize;
+
+use crate::{
+    application::TvApplication, channel_registry::ChannelRegistry, mediathek::ShowObject,
+    utils::tokio,
+};
+
+/// Runs a headless command to completion and converts its result into an exit code.
+pub fn run_headless(fut: impl Future<Output = eyre::Result<()>>) -> glib::ExitCode {
+    glib::MainContext::default().block_on(async move {
+        match fut.await {
+            Ok(()) => glib::ExitCode::SUCCESS,
+            Err(e) => {
+                tracing::error!("{e:?}");
+                glib::ExitCode::FAILURE
+            }
+        }
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct Channel {
+    id: String,
+    name: String,
+    stream_url: String,
+    /// The broadcaster from the channel registry.
+    group: Option<String>,
+    region: Option<String>,
+}
+
+/// Prints the live channels selected by the user.
+pub async fn list_channels(json: bool) -> eyre::Result<()> {
+    let application = TvApplication::get();
+    let channels = application.visible_live_channels().await;
+    // the loader only stores the error, so it must be checked to exit with a failure
+    if let Some(e) = application.live_channels_error() {
+        return Err(eyre::Report::new(e).wrap_err(gettext("Failed to load livestream channels")));
+    }
+
+    let channels = channels
+        .into_iter()
+        .map(|(channel_id, channel_info)| {
+            let entry = ChannelRegistry::get().find(channel_id.as_ref());
+            Channel {
+                id: channel_id.to_string(),
+                name: channel_info.name,
+                stream_url: channel_info.stream_url,
+                group: entry.and_then(|entry| entry.group.clone()),
+                region: entry.and_then(|entry| entry.region.clone()),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&channels)?);
+    } else {
+        for Channel {
+            id,
+            name,
+            stream_url,
+            ..
+        } in channels
+        {
+            println!("{id}\t{name}\t{stream_url}");
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResult {
+    channel: String,
+    topic: String,
+    title: String,
+    description: Option<String>,
+    timestamp: i64,
+    duration: u64,
+    video_url_high: Option<String>,
+    video_url_medium: Option<String>,
+    video_url_low: Option<String>,
+    subtitle_url: Option<String>,
+    website_url: Option<String>,
+}
+
+impl From<ShowObject> for SearchResult {
+    fn from(show: ShowObject) -> Self {
+        Self {
+            channel: show.channel(),
+            topic: show.topic(),
+            title: show.title(),
+            description: show.description(),
+            timestamp: show.timestamp(),
+            duration: show.duration_seconds(),
+            video_url_high: show.video_url_high(),
+            video_url_medium: show.video_url_medium(),
+            video_url_low: show.video_url_low(),
+            subtitle_url: show.subtitle_url(),
+            website_url: show.website_url(),
+        }
+    }
+}
+
+/// Searches the Mediathek and prints the results.
+pub async fn search(query: String, json: bool) -> eyre::Result<()> {
+    let client = TvApplication::get().mediathek();
+
+    let result = tokio(async move {
+        client
+            .query_string(&query, false)
+            .size(25)
+            .send()
+            .await
+            .wrap_err_with(|| gettext("Failed to query the MediathekViewWeb API"))
+    })
+    .await?;
+
+    let results = result
+        .results
+        .into_iter()
+        .map(|item| SearchResult::from(ShowObject::new(item)))
+        .collect::<Vec<_>>();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        for SearchResult {
+            channel,
+            topic,
+            title,
+            timestamp,
+            video_url_medium,
+            ..
+        } in results
+        {
+            let date = glib::DateTime::from_unix_local(timestamp)
+                .and_then(|datetime| datetime.format_iso8601())
+                .map(|date| date.to_string())
+                .unwrap_or_default();
+            println!(
+                "{channel}\t{date}\t{topic}\t{title}\t{}",
+                video_url_medium.unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,239 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! `televido://` links that can be shared and opened in Televido.
+//!
+//! Supported links:
+//! - `televido://live/<channel-id>` plays a live channel
+//! - `televido://mediathek/search?q=<query>&channel=<channel>` searches the Mediathek
+//! - `televido://mediathek/play?title=…&channel=…&high=…&medium=…&low=…&subtitles=…`
+//!   plays a show from the Mediathek
+
+use gettextrs::gettext;
+use reqwest::Url;
+
+use crate::{player::VideoInfo, settings::VideoQuality};
+
+pub const SCHEME: &str = "televido";
+
+#[derive(Debug)]
+pub enum DeepLink {
+    Live { channel_id: String },
+    Search { query_string: String },
+    Play(VideoInfo),
+}
+
+impl DeepLink {
+    pub fn parse(uri: &str) -> eyre::Result<Self> {
+        Self::parse_with_default_quality(uri, VideoQuality::for_channel)
+    }
+    /// Parses a link, using `default_quality` to select the quality of the video to play.
+    fn parse_with_default_quality(
+        uri: &str,
+        default_quality: impl FnOnce(&str) -> VideoQuality,
+    ) -> eyre::Result<Self> {
+        let invalid = || {
+            // translators: `{}` is replaced by the link, e.g. `televido://live/zdf`
+            eyre::Report::msg(gettext("Invalid link “{}”").replace("{}", uri))
+        };
+
+        let url = Url::parse(uri).map_err(|_| invalid())?;
+        if url.scheme() != SCHEME {
+            return Err(invalid());
+        }
+
+        let query = |key: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.into_owned())
+                .filter(|v| !v.is_empty())
+        };
+
+        match (url.host_str(), url.path().trim_matches('/')) {
+            (Some("live"), channel_id) if !channel_id.is_empty() => Ok(Self::Live {
+                channel_id: channel_id.to_owned(),
+            }),
+            (Some("mediathek"), "search") => {
+                let mut query_string = query("q").unwrap_or_default();
+                if let Some(channel) = query("channel") {
+                    // MediathekViewWeb syntax for restricting the search to a channel
+                    query_string = format!("!{} {query_string}", channel.to_lowercase());
+                }
+                Ok(Self::Search {
+                    query_string: query_string.trim().to_owned(),
+                })
+            }
+            (Some("mediathek"), "play") => {
+                let (uri_high, uri_medium, uri_low, subtitle_uri) = (
+                    query("high"),
+                    query("medium"),
+                    query("low"),
+                    query("subtitles"),
+                );
+                if uri_high.is_none() && uri_medium.is_none() && uri_low.is_none() {
+                    return Err(invalid());
+                }
+                // links might come from anywhere, so they must not open local files or other schemes
+                if ![&uri_high, &uri_medium, &uri_low, &subtitle_uri]
+                    .into_iter()
+                    .flatten()
+                    .all(|uri| is_web_url(uri))
+                {
+                    return Err(invalid());
+                }
+
+                let channel_id = query("channel").unwrap_or_default();
+                Ok(Self::Play(VideoInfo::mediathek_with_default_quality(
+                    default_quality(&channel_id),
+                    query("title").unwrap_or_default(),
+                    subtitle_uri,
+                    uri_high,
+                    uri_medium,
+                    uri_low,
+                    channel_id,
+                )))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn to_uri(&self) -> String {
+        let mut url = Url::parse(&format!("{SCHEME}://")).expect("scheme should be valid");
+
+        match self {
+            Self::Live { channel_id } => {
+                url.set_host(Some("live")).expect("host should be valid");
+                url.set_path(channel_id);
+            }
+            Self::Search { query_string } => {
+                url.set_host(Some("mediathek"))
+                    .expect("host should be valid");
+                url.set_path("search");
+                url.query_pairs_mut().append_pair("q", query_string);
+            }
+            Self::Play(VideoInfo::Mediathek {
+                title,
+                subtitle_uri,
+                uri_high,
+                uri_medium,
+                uri_low,
+                channel_id,
+                ..
+            }) => {
+                url.set_host(Some("mediathek"))
+                    .expect("host should be valid");
+                url.set_path("play");
+
+                let mut query = url.query_pairs_mut();
+                query.append_pair("title", title);
+                query.append_pair("channel", channel_id);
+                for (key, value) in [
+                    ("high", uri_high),
+                    ("medium", uri_medium),
+                    ("low", uri_low),
+                    ("subtitles", subtitle_uri),
+                ] {
+                    if let Some(value) = value {
+                        query.append_pair(key, value);
+                    }
+                }
+            }
+            Self::Play(VideoInfo::Live { channel_id, .. }) => {
+                return Self::Live {
+                    channel_id: channel_id.clone(),
+                }
+                .to_uri()
+            }
+        }
+
+        url.to_string()
+    }
+}
+
+fn is_web_url(uri: &str) -> bool {
+    Url::parse(uri).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(uri: &str) -> eyre::Result<DeepLink> {
+        DeepLink::parse_with_default_quality(uri, |_| VideoQuality::High)
+    }
+
+    #[test]
+    fn parse_live() {
+        let link = parse("televido://live/zdf").unwrap();
+        assert!(matches!(&link, DeepLink::Live { channel_id } if channel_id == "zdf"));
+        assert_eq!(link.to_uri(), "televido://live/zdf");
+    }
+
+    #[test]
+    fn parse_search() {
+        let link = parse("televido://mediathek/search?q=tatort&channel=ARD").unwrap();
+        assert!(matches!(
+            &link,
+            DeepLink::Search { query_string } if query_string == "!ard tatort"
+        ));
+
+        let DeepLink::Search { query_string } = parse(&link.to_uri()).unwrap() else {
+            panic!("expected a search link");
+        };
+        assert_eq!(query_string, "!ard tatort");
+    }
+
+    #[test]
+    fn parse_play() {
+        let link = parse(
+            "televido://mediathek/play?title=Tatort%3A+Der+Fall&channel=ARD\
+             &medium=https%3A%2F%2Fexample.org%2Fvideo.mp4%3Fa%3D1%26b%3D2\
+             &subtitles=https%3A%2F%2Fexample.org%2Fsubtitles.xml",
+        )
+        .unwrap();
+        let DeepLink::Play(VideoInfo::Mediathek {
+            title,
+            preferred_quality,
+            subtitle_uri,
+            uri_high,
+            uri_medium,
+            uri_low,
+            channel_id,
+        }) = &link
+        else {
+            panic!("expected a Mediathek video");
+        };
+        assert_eq!(title, "Tatort: Der Fall");
+        assert_eq!(channel_id, "ARD");
+        assert_eq!(uri_high, &None);
+        assert_eq!(
+            uri_medium.as_deref(),
+            Some("https://example.org/video.mp4?a=1&b=2")
+        );
+        assert_eq!(uri_low, &None);
+        assert_eq!(
+            subtitle_uri.as_deref(),
+            Some("https://example.org/subtitles.xml")
+        );
+        // the default quality isn't available
+        assert!(matches!(preferred_quality, VideoQuality::Medium));
+
+        assert_eq!(parse(&link.to_uri()).unwrap().to_uri(), link.to_uri());
+    }
+
+    #[test]
+    fn reject_invalid_links() {
+        for uri in [
+            "https://example.org/",
+            "televido://live/",
+            "televido://unknown/zdf",
+            "televido://mediathek/play?title=Test",
+            "televido://mediathek/play?high=file%3A%2F%2F%2Fetc%2Fpasswd",
+            "televido://mediathek/play?high=https%3A%2F%2Fexample.org%2Fa.mp4&low=smb%3A%2F%2Fhost%2Fb.mp4",
+            "televido://mediathek/play?high=https%3A%2F%2Fexample.org%2Fa.mp4&subtitles=file%3A%2F%2F%2Ftmp%2Fa",
+            "televido://mediathek/play?high=%2Fetc%2Fpasswd",
+        ] {
+            assert!(parse(uri).is_err(), "{uri} should be rejected");
+        }
+    }
+}
@@ -0,0 +1,182 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Errors of the web services used by the app, i.e. Zapp and MediathekViewWeb.
+
+use std::{error::Error, fmt::Display, future::Future, sync::Arc, time::Duration};
+
+use adw::prelude::*;
+use gettextrs::gettext;
+use reqwest::StatusCode;
+
+use crate::network;
+
+/// Transient errors are retried this many times.
+const MAX_RETRIES: u32 = 3;
+/// The delay before the first retry, which is doubled for each further retry.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorKind {
+    /// The server couldn't be reached, most likely because there is no network connection.
+    Offline,
+    Timeout,
+    Status(StatusCode),
+    /// The response had an unexpected format.
+    Decode,
+    GeoBlocked,
+    Other,
+}
+
+#[derive(Clone, Debug)]
+pub struct ApiError {
+    kind: ErrorKind,
+    source: Arc<dyn Error + Send + Sync>,
+}
+
+impl ApiError {
+    /// Classifies an error of an HTTP client based on the `reqwest` or `serde_json` error that caused it.
+    pub fn new(error: impl Error + Send + Sync + 'static) -> Self {
+        let mut kind = ErrorKind::Other;
+        let mut source: Option<&(dyn Error + 'static)> = Some(&error);
+
+        while let Some(e) = source {
+            if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+                kind = match e.status() {
+                    Some(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS) => ErrorKind::GeoBlocked,
+                    Some(status) => ErrorKind::Status(status),
+                    None if e.is_timeout() => ErrorKind::Timeout,
+                    None if e.is_connect() => ErrorKind::Offline,
+                    None if e.is_decode() => ErrorKind::Decode,
+                    None => ErrorKind::Other,
+                };
+                break;
+            }
+            if e.is::<serde_json::Error>() {
+                kind = ErrorKind::Decode;
+                break;
+            }
+            source = e.source();
+        }
+
+        Self {
+            kind,
+            source: Arc::new(error),
+        }
+    }
+    /// Finds the `ApiError` that caused a report.
+    pub fn find(report: &eyre::Report) -> Option<&Self> {
+        report.chain().find_map(|e| e.downcast_ref::<Self>())
+    }
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+    /// Whether the request might succeed if it is sent again.
+    pub fn is_transient(&self) -> bool {
+        match self.kind {
+            ErrorKind::Offline | ErrorKind::Timeout => true,
+            ErrorKind::Status(status) => {
+                status.is_server_error()
+                    || status == StatusCode::TOO_MANY_REQUESTS
+                    || status == StatusCode::REQUEST_TIMEOUT
+            }
+            ErrorKind::Decode | ErrorKind::GeoBlocked | ErrorKind::Other => false,
+        }
+    }
+    pub fn title(&self) -> String {
+        match self.kind {
+            ErrorKind::Offline => gettext("No Connection"),
+            ErrorKind::Timeout => gettext("Connection Timed Out"),
+            ErrorKind::Status(_) => gettext("Server Error"),
+            ErrorKind::Decode => gettext("Unexpected Response"),
+            ErrorKind::GeoBlocked => gettext("Not Available in Your Country"),
+            ErrorKind::Other => gettext("Something Went Wrong"),
+        }
+    }
+    pub fn description(&self) -> String {
+        match self.kind {
+            ErrorKind::Offline => gettext("Check your internet connection and try again."),
+            ErrorKind::Timeout => gettext("The server took too long to respond."),
+            // translators: `{}` is replaced by an HTTP status, e.g. `503 Service Unavailable`
+            ErrorKind::Status(status) => gettext("The server responded with status {}.")
+                .replace("{}", &status.to_string()),
+            ErrorKind::Decode => gettext(
+                "The response of the server could not be read. A newer version of the app might be required.",
+            ),
+            ErrorKind::GeoBlocked => {
+                gettext("The broadcaster only provides this content in some countries.")
+            }
+            ErrorKind::Other => gettext("See the terminal output for details."),
+        }
+    }
+    pub fn icon_name(&self) -> &'static str {
+        match self.kind {
+            ErrorKind::Offline | ErrorKind::Timeout => "network-offline-symbolic",
+            _ => "dialog-error-symbolic",
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            ErrorKind::Status(status) => write!(f, "{}: {status}", self.title()),
+            _ => f.write_str(&self.title()),
+        }
+    }
+}
+
+impl Error for ApiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::new(e)
+    }
+}
+
+/// Runs a request and retries it with exponential backoff if it fails with a transient error.
+///
+/// Requests aren't retried while the network is unavailable, so the error is shown immediately.
+/// This must be called within the tokio runtime.
+pub async fn retry<T, Fut>(mut request: impl FnMut() -> Fut) -> Result<T, ApiError>
+where
+    Fut: Future<Output = Result<T, ApiError>>,
+{
+    let mut delay = INITIAL_RETRY_DELAY;
+    let mut retries = 0;
+
+    loop {
+        match request().await {
+            Err(e) if e.is_transient() && retries < MAX_RETRIES && network::was_online() => {
+                tracing::warn!("{e}, retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+
+                delay *= 2;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Shows an error on a status page, which should contain a button to retry.
+pub fn show_on_status_page(report: &eyre::Report, status_page: &adw::StatusPage) {
+    tracing::error!("{report:?}");
+
+    match ApiError::find(report) {
+        Some(e) => {
+            status_page.set_icon_name(Some(e.icon_name()));
+            status_page.set_title(&e.title());
+            status_page.set_description(Some(&e.description()));
+        }
+        None => {
+            status_page.set_icon_name(Some("dialog-error-symbolic"));
+            status_page.set_title(&report.to_string());
+            status_page.set_description(Some(&gettext("See the terminal output for details.")));
+        }
+    }
+}
@@ -0,0 +1,408 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Variant selection for HLS live streams.
+
+use std::{fmt::Display, str::FromStr};
+
+use gettextrs::gettext;
+use reqwest::Url;
+
+use crate::{
+    http, network,
+    settings::{TvSettings, VideoQuality},
+    utils::tokio,
+};
+
+/// Variants up to this height are used for [`VideoQuality::Medium`].
+const MEDIUM_QUALITY_HEIGHT: u32 = 720;
+
+/// A stream from an HLS master playlist.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    pub uri: String,
+    pub bandwidth: u64,
+    pub resolution: Option<(u32, u32)>,
+}
+
+impl Variant {
+    pub fn height(&self) -> Option<u32> {
+        self.resolution.map(|(_, height)| height)
+    }
+    /// A label like `720p · 3.4 Mbit/s`.
+    pub fn label(&self) -> String {
+        let bitrate = format!("{:.1} Mbit/s", self.bandwidth as f64 / 1_000_000.0);
+
+        match self.height() {
+            Some(height) => format!("{height}p · {bitrate}"),
+            None => bitrate,
+        }
+    }
+}
+
+/// Downloads a master playlist and returns its variants, sorted by descending bandwidth.
+///
+/// Returns an empty list if the URI doesn't point to a master playlist.
+pub async fn fetch_variants(uri: String) -> eyre::Result<Vec<Variant>> {
+    tokio(async move {
+        let response = http::client().get(&uri).send().await?.error_for_status()?;
+        let base = response.url().clone();
+        let playlist = response.text().await?;
+
+        Ok(parse_master_playlist(&playlist, &base))
+    })
+    .await
+}
+
+pub fn parse_master_playlist(playlist: &str, base: &Url) -> Vec<Variant> {
+    let mut variants = Vec::<Variant>::new();
+    let mut lines = playlist.lines().map(str::trim);
+
+    while let Some(line) = lines.next() {
+        let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") else {
+            continue;
+        };
+        let Some(uri) = lines
+            .by_ref()
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+        else {
+            break;
+        };
+        let Ok(uri) = base.join(uri) else {
+            continue;
+        };
+
+        let mut bandwidth = None;
+        let mut resolution = None;
+        for (key, value) in split_attributes(attributes) {
+            match key {
+                "BANDWIDTH" => bandwidth = value.parse().ok(),
+                "RESOLUTION" => {
+                    resolution = value
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                }
+                _ => (),
+            }
+        }
+        let Some(bandwidth) = bandwidth else {
+            continue;
+        };
+
+        // variants that only differ in their audio or codecs aren't useful to choose from
+        if variants
+            .iter()
+            .any(|v| v.bandwidth == bandwidth && v.resolution == resolution)
+        {
+            continue;
+        }
+        variants.push(Variant {
+            uri: uri.to_string(),
+            bandwidth,
+            resolution,
+        });
+    }
+
+    variants.sort_by(|a, b| b.bandwidth.cmp(&a.bandwidth));
+    variants
+}
+
+/// Returns the variant with the lowest bandwidth that contains a video.
+///
+/// Variants without a resolution are often audio-only, so they are only used if no other variant exists.
+pub fn lowest_video_variant(variants: &[Variant]) -> Option<&Variant> {
+    variants
+        .iter()
+        .rev()
+        .find(|variant| variant.resolution.is_some())
+        .or(variants.last())
+}
+
+/// Splits an attribute list like `BANDWIDTH=1000,CODECS="avc1,mp4a"`.
+fn split_attributes(attributes: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut rest = attributes;
+
+    std::iter::from_fn(move || {
+        let (key, value) = rest.split_once('=')?;
+        let (value, tail) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, tail) = quoted.split_once('"').unwrap_or((quoted, ""));
+                (value, tail.strip_prefix(',').unwrap_or(tail))
+            }
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        rest = tail;
+        Some((key.trim(), value))
+    })
+}
+
+/// How the variant of a live stream is chosen.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VariantPreference {
+    /// Based on the `default-playback-quality` setting.
+    #[default]
+    Default,
+    /// The master playlist is played and the player adapts to the available bandwidth.
+    Auto,
+    /// A fixed variant, identified by its bandwidth.
+    Pin(u64),
+    /// The best variant up to the given height.
+    MaxHeight(u32),
+}
+
+impl VariantPreference {
+    pub fn for_channel(channel_id: &str) -> Self {
+        TvSettings::get()
+            .live_stream_variants()
+            .get(channel_id)
+            .and_then(|preference| preference.parse().ok())
+            .unwrap_or_default()
+    }
+    pub fn save(self, channel_id: &str) {
+        if self == Self::for_channel(channel_id) {
+            return;
+        }
+
+        let settings = TvSettings::get();
+        let mut preferences = settings.live_stream_variants();
+
+        if self == Self::Default {
+            preferences.remove(channel_id);
+        } else {
+            preferences.insert(channel_id.to_owned(), self.to_string());
+        }
+        settings.set_live_stream_variants(preferences);
+    }
+    /// Whether the master playlist must be loaded to choose a variant.
+    pub fn needs_variants(self) -> bool {
+        if network::bandwidth_saver() {
+            return true;
+        }
+        match self {
+            Self::Default => VideoQuality::default_playback() != VideoQuality::High,
+            Self::Auto => false,
+            Self::Pin(_) | Self::MaxHeight(_) => true,
+        }
+    }
+    /// Returns the variant that should be played or `None` to play the master playlist.
+    ///
+    /// The preference is ignored while the bandwidth saver is active.
+    pub fn select(self, variants: &[Variant]) -> Option<&Variant> {
+        if network::bandwidth_saver() {
+            return lowest_video_variant(variants);
+        }
+        self.select_with_default_quality(variants, VideoQuality::default_playback())
+    }
+    fn select_with_default_quality(
+        self,
+        variants: &[Variant],
+        default_quality: VideoQuality,
+    ) -> Option<&Variant> {
+        match self {
+            Self::Default => match default_quality {
+                VideoQuality::High => None,
+                VideoQuality::Medium => Self::MaxHeight(MEDIUM_QUALITY_HEIGHT)
+                    .select_with_default_quality(variants, default_quality),
+                VideoQuality::Low => lowest_video_variant(variants),
+            },
+            Self::Auto => None,
+            Self::Pin(bandwidth) => variants.iter().find(|v| v.bandwidth == bandwidth),
+            Self::MaxHeight(max_height) => variants
+                .iter()
+                .find(|v| v.height().is_some_and(|height| height <= max_height))
+                .or_else(|| lowest_video_variant(variants)),
+        }
+    }
+    pub fn label(self) -> String {
+        match self {
+            Self::Default => gettext("Default Quality"),
+            Self::Auto => gettext("Automatic"),
+            Self::Pin(bandwidth) => format!("{:.1} Mbit/s", bandwidth as f64 / 1_000_000.0),
+            // translators: `{}` is replaced by a resolution, e.g. `720`
+            Self::MaxHeight(height) => gettext("Up to {}p").replace("{}", &height.to_string()),
+        }
+    }
+}
+
+impl FromStr for VariantPreference {
+    type Err = eyre::Report;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            _ if s.is_empty() || s == "default" => Ok(Self::Default),
+            _ if s == "auto" => Ok(Self::Auto),
+            Some(("pin", bandwidth)) => Ok(Self::Pin(bandwidth.parse()?)),
+            Some(("max", height)) => Ok(Self::MaxHeight(height.parse()?)),
+            _ => Err(eyre::eyre!("invalid value for stream variant: \"{s}\"")),
+        }
+    }
+}
+
+impl Display for VariantPreference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Default => f.write_str("default"),
+            Self::Auto => f.write_str("auto"),
+            Self::Pin(bandwidth) => write!(f, "pin:{bandwidth}"),
+            Self::MaxHeight(height) => write!(f, "max:{height}"),
+        }
+    }
+}
+
+/// Resolves the URI of a live stream according to the user's preference for the channel.
+pub async fn resolve_live_uri(channel_id: &str, uri: String) -> String {
+    let preference = VariantPreference::for_channel(channel_id);
+    if !preference.needs_variants() {
+        return uri;
+    }
+
+    match fetch_variants(uri.clone()).await {
+        Ok(variants) => preference
+            .select(&variants)
+            .map(|variant| variant.uri.clone())
+            .unwrap_or(uri),
+        Err(e) => {
+            tracing::warn!("failed to load HLS variants: {e:?}");
+            uri
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_quoted_attributes() {
+        let attributes = split_attributes(
+            r#"BANDWIDTH=3500000,CODECS="avc1.4d401f,mp4a.40.2",RESOLUTION=1280x720,NAME="a=b""#,
+        )
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            attributes,
+            [
+                ("BANDWIDTH", "3500000"),
+                ("CODECS", "avc1.4d401f,mp4a.40.2"),
+                ("RESOLUTION", "1280x720"),
+                ("NAME", "a=b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_master() {
+        let base = Url::parse("https://example.org/live/master.m3u8?token=1").unwrap();
+        let playlist = "#EXTM3U\r\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=1000000,CODECS=\"avc1.4d401e,mp4a.40.2\",RESOLUTION=640x360\r\n\
+            low/index.m3u8\r\n\
+            #EXT-X-STREAM-INF:CODECS=\"avc1.640028,mp4a.40.2\",RESOLUTION=1920x1080\r\n\
+            missing-bandwidth.m3u8\r\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=5000000,CODECS=\"avc1.640028,mp4a.40.2\",RESOLUTION=1280x720\r\n\
+            /hd/index.m3u8\r\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=5000000,CODECS=\"avc1.640028,ec-3\",RESOLUTION=1280x720\r\n\
+            /hd/ec3.m3u8\r\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=200000\r\n\
+            https://cdn.example.org/audio.m3u8\r\n";
+
+        assert_eq!(
+            parse_master_playlist(playlist, &base),
+            [
+                Variant {
+                    uri: "https://example.org/hd/index.m3u8".to_owned(),
+                    bandwidth: 5_000_000,
+                    resolution: Some((1280, 720)),
+                },
+                Variant {
+                    uri: "https://example.org/live/low/index.m3u8".to_owned(),
+                    bandwidth: 1_000_000,
+                    resolution: Some((640, 360)),
+                },
+                Variant {
+                    uri: "https://cdn.example.org/audio.m3u8".to_owned(),
+                    bandwidth: 200_000,
+                    resolution: None,
+                },
+            ]
+        );
+    }
+
+    fn variants() -> Vec<Variant> {
+        [
+            (5_000_000, Some((1920, 1080))),
+            (3_000_000, Some((1280, 720))),
+            (1_000_000, Some((640, 360))),
+            (200_000, None),
+        ]
+        .into_iter()
+        .map(|(bandwidth, resolution)| Variant {
+            uri: format!("https://example.org/{bandwidth}.m3u8"),
+            bandwidth,
+            resolution,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn select_variant() {
+        let variants = variants();
+        let select = |preference: VariantPreference, default_quality| {
+            preference
+                .select_with_default_quality(&variants, default_quality)
+                .map(|variant| variant.bandwidth)
+        };
+
+        assert_eq!(select(VariantPreference::Default, VideoQuality::High), None);
+        assert_eq!(
+            select(VariantPreference::Default, VideoQuality::Medium),
+            Some(3_000_000)
+        );
+        assert_eq!(
+            select(VariantPreference::Default, VideoQuality::Low),
+            Some(1_000_000)
+        );
+        assert_eq!(select(VariantPreference::Auto, VideoQuality::Low), None);
+        assert_eq!(
+            select(VariantPreference::Pin(200_000), VideoQuality::High),
+            Some(200_000)
+        );
+        assert_eq!(select(VariantPreference::Pin(42), VideoQuality::High), None);
+        assert_eq!(
+            select(VariantPreference::MaxHeight(1080), VideoQuality::High),
+            Some(5_000_000)
+        );
+        assert_eq!(
+            select(VariantPreference::MaxHeight(480), VideoQuality::High),
+            Some(1_000_000)
+        );
+        // no variant is small enough, so the lowest one with a video is used
+        assert_eq!(
+            select(VariantPreference::MaxHeight(240), VideoQuality::High),
+            Some(1_000_000)
+        );
+    }
+
+    #[test]
+    fn lowest_video() {
+        let variants = variants();
+        assert_eq!(
+            lowest_video_variant(&variants).map(|variant| variant.bandwidth),
+            Some(1_000_000)
+        );
+
+        let audio_only = &variants[3..];
+        assert_eq!(
+            lowest_video_variant(audio_only).map(|variant| variant.bandwidth),
+            Some(200_000)
+        );
+
+        assert_eq!(lowest_video_variant(&[]), None);
+    }
+
+    #[test]
+    fn parse_media_playlist() {
+        let base = Url::parse("https://example.org/index.m3u8").unwrap();
+        let playlist = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nsegment0.ts\n";
+
+        assert_eq!(parse_master_playlist(playlist, &base), []);
+    }
+}
@@ -0,0 +1,207 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Configuration that is shared by all HTTP clients, i.e. the user agent and the proxy.
+
+use std::sync::Mutex;
+
+use adw::{gio, glib, prelude::*};
+use reqwest::Url;
+
+use crate::{
+    config::{APP_ID, PROJECT_URL, VERSION},
+    settings::TvSettings,
+};
+
+/// The system proxy is looked up for this URI, so proxies that are configured per host aren't supported.
+const PROXY_LOOKUP_URI: &str = "https://mediathekviewweb.de/";
+
+static PROXY: Mutex<Proxy> = Mutex::new(Proxy::Environment);
+static CLIENT: Mutex<Option<reqwest::Client>> = Mutex::new(None);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Proxy {
+    /// Proxies from environment variables like `https_proxy` are used.
+    Environment,
+    /// Connections are made directly, except for the playback, which falls back to the system proxy.
+    Direct,
+    Url(String),
+}
+
+pub fn user_agent() -> String {
+    format!("{APP_ID}/{VERSION} ({PROJECT_URL})")
+}
+
+/// Creates a client builder that uses the app's user agent and the configured proxy.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder().user_agent(user_agent());
+
+    match proxy() {
+        Proxy::Environment => builder,
+        Proxy::Direct => builder.no_proxy(),
+        Proxy::Url(url) => match reqwest::Proxy::all(&url) {
+            Ok(proxy) => builder.proxy(proxy),
+            Err(e) => {
+                tracing::error!("invalid proxy URL {url}: {e}");
+                builder
+            }
+        },
+    }
+}
+
+/// Returns a shared client, which is recreated when the proxy changes.
+pub fn client() -> reqwest::Client {
+    CLIENT
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| {
+            client_builder()
+                .build()
+                .expect("failed to initialize HTTP client")
+        })
+        .clone()
+}
+
+pub fn proxy() -> Proxy {
+    PROXY.lock().unwrap().clone()
+}
+
+/// Looks up the proxy that is configured in the preferences or in the system settings.
+async fn resolve_proxy() -> Proxy {
+    let settings = TvSettings::get();
+
+    match settings.proxy_mode().as_str() {
+        "none" => Proxy::Direct,
+        "manual" => match settings.proxy_url().trim() {
+            "" => Proxy::Environment,
+            url if is_supported_proxy(url) => Proxy::Url(url.to_owned()),
+            url => {
+                tracing::error!("unsupported proxy URL {url}, connecting directly");
+                Proxy::Direct
+            }
+        },
+        _ => match gio::ProxyResolver::default()
+            .lookup_future(PROXY_LOOKUP_URI)
+            .await
+        {
+            Ok(proxies) => match proxies.into_iter().find(|proxy| proxy != "direct://") {
+                Some(proxy) if is_supported_proxy(&proxy) => Proxy::Url(proxy.into()),
+                Some(proxy) => {
+                    tracing::error!("unsupported system proxy {proxy}, connecting directly");
+                    Proxy::Direct
+                }
+                None => Proxy::Environment,
+            },
+            Err(e) => {
+                tracing::error!("failed to look up the system proxy: {e}");
+                Proxy::Environment
+            }
+        },
+    }
+}
+
+/// Only HTTP proxies are supported by `reqwest` (without the `socks` feature) and by GStreamer's `souphttpsrc`.
+pub fn is_supported_proxy(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Applies the configured proxy to all HTTP clients and HTTP sources of GStreamer that are created afterwards.
+///
+/// Returns `false` if the proxy didn't change.
+pub async fn update_proxy() -> bool {
+    let proxy = resolve_proxy().await;
+    if proxy == self::proxy() {
+        return false;
+    }
+    tracing::debug!("using proxy {proxy:?}");
+
+    *PROXY.lock().unwrap() = proxy;
+    *CLIENT.lock().unwrap() = None;
+
+    true
+}
+
+/// Calls `f` when the proxy settings of the app or of GNOME change.
+pub fn connect_proxy_settings_changed(f: impl Fn() + Clone + 'static) {
+    let settings = TvSettings::get();
+    settings.connect_proxy_mode_changed({
+        let f = f.clone();
+        move |_| f()
+    });
+    settings.connect_proxy_url_changed({
+        let f = f.clone();
+        move |_| f()
+    });
+
+    let Some(schema_source) = gio::SettingsSchemaSource::default() else {
+        return;
+    };
+    for schema in [
+        "org.gnome.system.proxy",
+        "org.gnome.system.proxy.http",
+        "org.gnome.system.proxy.https",
+    ] {
+        if schema_source.lookup(schema, true).is_none() {
+            continue;
+        }
+        let gnome_settings = gio::Settings::new(schema);
+        gnome_settings.connect_changed(None, {
+            let f = f.clone();
+            move |_, _| f()
+        });
+        // the settings object must be kept alive to receive signals
+        std::mem::forget(gnome_settings);
+    }
+}
+
+/// Sets the configured proxy on GStreamer's HTTP sources.
+///
+/// The playbin of the player isn't accessible, so the sources are configured when they are created
+/// instead of using its `source-setup` signal.
+pub fn setup_gstreamer_proxy() {
+    // the tracer must be kept alive to receive hooks
+    std::mem::forget(glib::Object::new::<ProxyTracer>());
+}
+
+glib::wrapper! {
+    struct ProxyTracer(ObjectSubclass<imp::ProxyTracer>)
+        @extends gst::Tracer, gst::Object;
+}
+
+mod imp {
+    use gst::subclass::prelude::*;
+
+    use super::*;
+
+    #[derive(Default)]
+    pub struct ProxyTracer;
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ProxyTracer {
+        const NAME: &'static str = "TvProxyTracer";
+        type Type = super::ProxyTracer;
+        type ParentType = gst::Tracer;
+    }
+
+    impl ObjectImpl for ProxyTracer {
+        fn constructed(&self) {
+            self.parent_constructed();
+            self.register_hook(TracerHook::ElementNew);
+        }
+    }
+    impl GstObjectImpl for ProxyTracer {}
+    impl TracerImpl for ProxyTracer {
+        fn element_new(&self, _ts: u64, element: &gst::Element) {
+            if !element
+                .factory()
+                .is_some_and(|factory| factory.name() == "souphttpsrc")
+            {
+                return;
+            }
+            // without a proxy, `souphttpsrc` uses the system proxy
+            if let Proxy::Url(url) = proxy() {
+                element.set_property("proxy", url);
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{collections::HashMap, fmt::Write};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaylistEntry {
+    pub tvg_id: String,
+    pub tvg_name: String,
+    pub tvg_logo: Option<String>,
+    pub group_title: Option<String>,
+    pub url: String,
+}
+
+/// Serializes the entries as an extended M3U playlist.
+pub fn write_playlist(entries: &[PlaylistEntry]) -> String {
+    let mut playlist = String::from("#EXTM3U\n");
+
+    for PlaylistEntry {
+        tvg_id,
+        tvg_name,
+        tvg_logo,
+        group_title,
+        url,
+    } in entries
+    {
+        write!(
+            playlist,
+            "#EXTINF:-1 tvg-id=\"{}\" tvg-name=\"{}\"",
+            attribute(tvg_id),
+            attribute(tvg_name)
+        )
+        .unwrap();
+        if let Some(tvg_logo) = tvg_logo {
+            write!(playlist, " tvg-logo=\"{}\"", attribute(tvg_logo)).unwrap();
+        }
+        if let Some(group_title) = group_title {
+            write!(playlist, " group-title=\"{}\"", attribute(group_title)).unwrap();
+        }
+        writeln!(playlist, ",{}", tvg_name.replace('\n', " ")).unwrap();
+        writeln!(playlist, "{url}").unwrap();
+    }
+
+    playlist
+}
+
+/// Parses an (extended) M3U playlist.
+///
+/// Entries without an `#EXTINF` line use their URL as name.
+pub fn parse_playlist(playlist: &str) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut extinf = None;
+
+    for line in playlist.lines().map(str::trim) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            extinf = Some(parse_extinf(info));
+        } else if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else {
+            let (mut attributes, title) = extinf.take().unwrap_or_default();
+
+            let tvg_name = attributes
+                .remove("tvg-name")
+                .filter(|name| !name.is_empty())
+                .or_else(|| (!title.is_empty()).then_some(title))
+                .unwrap_or_else(|| line.to_owned());
+
+            entries.push(PlaylistEntry {
+                tvg_id: attributes.remove("tvg-id").unwrap_or_default(),
+                tvg_name,
+                tvg_logo: attributes
+                    .remove("tvg-logo")
+                    .filter(|logo| !logo.is_empty()),
+                group_title: attributes
+                    .remove("group-title")
+                    .filter(|group| !group.is_empty()),
+                url: line.to_owned(),
+            });
+        }
+    }
+
+    entries
+}
+
+// `#EXTINF:<duration> <key>="<value>" ...,<title>`
+fn parse_extinf(info: &str) -> (HashMap<String, String>, String) {
+    let mut in_quotes = false;
+    let title_start = info
+        .char_indices()
+        .find(|&(_, c)| {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            }
+            c == ',' && !in_quotes
+        })
+        .map(|(i, _)| i)
+        .unwrap_or(info.len());
+    let (mut head, title) = info.split_at(title_start);
+    let title = title.strip_prefix(',').unwrap_or(title).trim().to_owned();
+
+    let mut attributes = HashMap::new();
+    while let Some(eq) = head.find("=\"") {
+        let key = head[..eq].rsplit(' ').next().unwrap_or_default().trim();
+        let value_start = eq + 2;
+        let Some(value_len) = head[value_start..].find('"') else {
+            break;
+        };
+
+        attributes.insert(
+            key.to_owned(),
+            head[value_start..value_start + value_len].to_owned(),
+        );
+        head = &head[value_start + value_len + 1..];
+    }
+
+    (attributes, title)
+}
+
+// M3U doesn't support escaping, so characters that would end the attribute are replaced
+fn attribute(value: &str) -> String {
+    value.replace('"', "'").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_quoted_attributes() {
+        let entries = parse_playlist(
+            "#EXTM3U\n\
+             #EXTINF:-1 tvg-id=\"zdf.de\" tvg-name=\"ZDF, HD\" tvg-logo=\"https://example.org/zdf.png?a=1,2\" group-title=\"ZDF\",ZDF HD\n\
+             https://example.org/zdf.m3u8\n",
+        );
+
+        assert_eq!(
+            entries,
+            [PlaylistEntry {
+                tvg_id: "zdf.de".to_owned(),
+                tvg_name: "ZDF, HD".to_owned(),
+                tvg_logo: Some("https://example.org/zdf.png?a=1,2".to_owned()),
+                group_title: Some("ZDF".to_owned()),
+                url: "https://example.org/zdf.m3u8".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_missing_attributes() {
+        let entries = parse_playlist(
+            "#EXTM3U\r\n\
+             #EXTINF:-1,Das Erste\r\n\
+             https://example.org/daserste.m3u8\r\n\
+             \r\n\
+             https://example.org/arte.m3u8\r\n",
+        );
+
+        assert_eq!(
+            entries,
+            [
+                PlaylistEntry {
+                    tvg_id: String::new(),
+                    tvg_name: "Das Erste".to_owned(),
+                    tvg_logo: None,
+                    group_title: None,
+                    url: "https://example.org/daserste.m3u8".to_owned(),
+                },
+                PlaylistEntry {
+                    tvg_id: String::new(),
+                    tvg_name: "https://example.org/arte.m3u8".to_owned(),
+                    tvg_logo: None,
+                    group_title: None,
+                    url: "https://example.org/arte.m3u8".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let entries = [
+            PlaylistEntry {
+                tvg_id: "3sat".to_owned(),
+                tvg_name: "3sat, Kultur".to_owned(),
+                tvg_logo: Some("file:///tmp/3sat.svg".to_owned()),
+                group_title: Some("ZDF".to_owned()),
+                url: "https://example.org/3sat.m3u8".to_owned(),
+            },
+            PlaylistEntry {
+                tvg_id: "phoenix".to_owned(),
+                tvg_name: "phoenix".to_owned(),
+                tvg_logo: None,
+                group_title: None,
+                url: "https://example.org/phoenix.m3u8?a=1&b=2".to_owned(),
+            },
+        ];
+
+        assert_eq!(parse_playlist(&write_playlist(&entries)), entries);
+    }
+
+    #[test]
+    fn write_sanitizes_attributes() {
+        let entries = [PlaylistEntry {
+            tvg_id: "test".to_owned(),
+            tvg_name: "A \"quoted\"\nname".to_owned(),
+            tvg_logo: None,
+            group_title: None,
+            url: "https://example.org/test.m3u8".to_owned(),
+        }];
+
+        assert_eq!(
+            parse_playlist(&write_playlist(&entries))[0].tvg_name,
+            "A 'quoted' name"
+        );
+    }
+}
@@ -0,0 +1,308 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::{Path, PathBuf};
+
+use adw::{gdk::gdk_pixbuf, gio, glib, prelude::*};
+use eyre::WrapErr;
+use tracing::error;
+
+use crate::{
+    application::TvApplication,
+    channel_icons::export_channel_icon,
+    channel_registry::ChannelRegistry,
+    http,
+    settings::TvSettings,
+    utils::tokio,
+    zapp::{ChannelId, ChannelInfo},
+};
+
+use self::m3u::PlaylistEntry;
+
+pub mod m3u;
+pub mod server;
+pub mod xmltv;
+
+/// Prefix of the IDs of channels that were imported from a playlist.
+const CUSTOM_CHANNEL_PREFIX: &str = "custom:";
+
+/// A live channel that was imported from a user-provided playlist.
+#[derive(Clone, Debug, PartialEq, glib::Variant)]
+pub struct CustomChannel {
+    pub id: ChannelId,
+    pub name: String,
+    pub stream_url: String,
+    /// Path to the locally stored logo or an empty string.
+    pub logo: String,
+}
+
+pub fn is_custom_channel(channel_id: &ChannelId) -> bool {
+    channel_id.as_ref().starts_with(CUSTOM_CHANNEL_PREFIX)
+}
+
+pub fn custom_channel(channel_id: &str) -> Option<CustomChannel> {
+    TvSettings::get()
+        .custom_channels()
+        .into_iter()
+        .find(|channel| channel.id.as_ref() == channel_id)
+}
+
+/// Returns the imported channels in the format used by the Zapp API.
+pub fn custom_channel_infos() -> impl Iterator<Item = (ChannelId, ChannelInfo)> {
+    TvSettings::get()
+        .custom_channels()
+        .into_iter()
+        .map(|channel| {
+            (
+                channel.id,
+                ChannelInfo {
+                    name: channel.name,
+                    stream_url: channel.stream_url,
+                },
+            )
+        })
+}
+
+/// Imports the entries of an M3U playlist as custom live channels.
+///
+/// Returns the number of imported channels.
+pub async fn import_m3u(path: impl AsRef<Path>) -> eyre::Result<usize> {
+    let path = path.as_ref().to_owned();
+    let playlist = tokio({
+        let path = path.clone();
+        async move { tokio::fs::read_to_string(path).await }
+    })
+    .await
+    .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let base_dir = path.parent().map(Path::to_owned).unwrap_or_default();
+
+    let settings = TvSettings::get();
+    let mut custom_channels = settings.custom_channels();
+    let mut live_channels = settings.live_channels();
+    let mut imported = 0;
+
+    for entry in m3u::parse_playlist(&playlist) {
+        let id = ChannelId::from(format!(
+            "{CUSTOM_CHANNEL_PREFIX}{}",
+            slugify(if entry.tvg_id.is_empty() {
+                &entry.tvg_name
+            } else {
+                &entry.tvg_id
+            })
+        ));
+        if custom_channels.iter().any(|channel| channel.id == id) {
+            continue;
+        }
+
+        let logo = match entry.tvg_logo {
+            Some(logo) => match import_logo(&id, &logo, &base_dir).await {
+                Ok(path) => path.to_string_lossy().into_owned(),
+                Err(e) => {
+                    error!("{e:?}");
+                    String::new()
+                }
+            },
+            None => String::new(),
+        };
+
+        // relative entries are file paths relative to the playlist
+        let stream_url = if entry.url.contains("://") {
+            entry.url
+        } else {
+            gio::File::for_path(base_dir.join(&entry.url))
+                .uri()
+                .to_string()
+        };
+
+        // an empty list means that all channels are visible
+        if !live_channels.is_empty() {
+            live_channels.push(id.clone());
+        }
+        custom_channels.push(CustomChannel {
+            id,
+            name: entry.tvg_name,
+            stream_url,
+            logo,
+        });
+        imported += 1;
+    }
+
+    settings.set_custom_channels(custom_channels);
+    settings.set_live_channels(live_channels);
+
+    Ok(imported)
+}
+
+pub fn remove_custom_channel(channel_id: &ChannelId) {
+    let settings = TvSettings::get();
+
+    let mut custom_channels = settings.custom_channels();
+    if let Some(pos) = custom_channels.iter().position(|c| &c.id == channel_id) {
+        let channel = custom_channels.remove(pos);
+        if !channel.logo.is_empty() {
+            if let Err(e) = std::fs::remove_file(&channel.logo) {
+                error!("failed to remove channel logo: {e}");
+            }
+        }
+    }
+    settings.set_custom_channels(custom_channels);
+
+    let mut live_channels = settings.live_channels();
+    live_channels.retain(|id| id != channel_id);
+    settings.set_live_channels(live_channels);
+}
+
+/// Stores the logo of an imported channel locally.
+async fn import_logo(channel_id: &ChannelId, logo: &str, base_dir: &Path) -> eyre::Result<PathBuf> {
+    let logo = logo.to_owned();
+    let extension = Path::new(logo.split(['?', '#']).next().unwrap_or_default())
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .filter(|ext| {
+            matches!(
+                ext.as_str(),
+                "png" | "jpg" | "jpeg" | "svg" | "webp" | "gif"
+            )
+        });
+    let mut logo_path = glib::user_data_dir()
+        .join("televido/custom-channels")
+        .join(slugify(channel_id.as_ref()));
+    if let Some(extension) = extension {
+        logo_path.set_extension(extension);
+    }
+
+    let source = if logo.starts_with("http://") || logo.starts_with("https://") {
+        None
+    } else if logo.starts_with("file://") {
+        gio::File::for_uri(&logo).path()
+    } else {
+        Some(base_dir.join(&logo))
+    };
+
+    tokio({
+        let logo_path = logo_path.clone();
+        async move {
+            tokio::fs::create_dir_all(logo_path.parent().unwrap()).await?;
+
+            match source {
+                Some(source) => {
+                    // the logo is served by the IPTV server, so playlists must not be able to
+                    // reference arbitrary files
+                    if let Err(e) = gdk_pixbuf::Pixbuf::from_file(&source) {
+                        return Err(eyre::Report::new(e)
+                            .wrap_err(format!("{} is not an image", source.display())));
+                    }
+                    tokio::fs::copy(source, &logo_path).await?;
+                }
+                None => {
+                    let data = http::client()
+                        .get(&logo)
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .bytes()
+                        .await?;
+                    tokio::fs::write(&logo_path, data).await?;
+                }
+            }
+
+            Ok::<(), eyre::Report>(())
+        }
+    })
+    .await
+    .wrap_err_with(|| format!("Failed to import channel logo for {channel_id}"))?;
+
+    Ok(logo_path)
+}
+
+fn slugify(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Directory where channel logos are exported to so that playlists can reference them.
+pub fn channel_logo_dir() -> PathBuf {
+    glib::user_data_dir().join("televido/channel-logos")
+}
+
+/// Builds the playlist entries for the live channels selected by the user.
+///
+/// Fails if the live channels couldn't be loaded.
+pub async fn live_playlist_entries() -> eyre::Result<Vec<PlaylistEntry>> {
+    let application = TvApplication::get();
+    let channels = application.visible_live_channels().await;
+    // the loader only stores the error, so an empty playlist isn't served while offline
+    if let Some(e) = application.live_channels_error() {
+        return Err(eyre::Report::new(e).wrap_err("Failed to load livestream channels"));
+    }
+
+    let logo_dir = channel_logo_dir();
+    let mut entries = Vec::new();
+
+    for (channel_id, channel_info) in channels {
+        let tvg_logo = match custom_channel(channel_id.as_ref()) {
+            Some(channel) => (!channel.logo.is_empty()).then(|| PathBuf::from(channel.logo)),
+            None => export_channel_icon(channel_id.as_ref(), &logo_dir).await?,
+        }
+        .map(|path| gio::File::for_path(path).uri().to_string());
+
+        entries.push(PlaylistEntry {
+            tvg_id: channel_id.to_string(),
+            tvg_name: channel_info.name,
+            tvg_logo,
+            group_title: ChannelRegistry::get()
+                .find(channel_id.as_ref())
+                .and_then(|entry| entry.group.clone()),
+            url: channel_info.stream_url,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Builds the programme guide for the given channels from the cached EPG data.
+pub async fn live_guide(channels: &[PlaylistEntry]) -> eyre::Result<String> {
+    let application = TvApplication::get();
+    let mut shows = Vec::new();
+
+    for channel in channels {
+        shows.extend(
+            application
+                .shows(&ChannelId::from(channel.tvg_id.clone()))
+                .await
+                .wrap_err_with(|| format!("Failed to load shows for channel {}", channel.tvg_id))?,
+        );
+    }
+
+    Ok(xmltv::write_guide(channels, &shows))
+}
+
+pub async fn export_m3u(path: impl AsRef<Path>) -> eyre::Result<()> {
+    let playlist = m3u::write_playlist(&live_playlist_entries().await?);
+
+    write_file(path.as_ref(), playlist).await
+}
+
+pub async fn export_xmltv(path: impl AsRef<Path>) -> eyre::Result<()> {
+    let guide = live_guide(&live_playlist_entries().await?).await?;
+
+    write_file(path.as_ref(), guide).await
+}
+
+async fn write_file(path: &Path, contents: String) -> eyre::Result<()> {
+    let path = path.to_owned();
+
+    tokio({
+        let path = path.clone();
+        async move { tokio::fs::write(path, contents).await }
+    })
+    .await
+    .wrap_err_with(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,386 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! A minimal HTTP server that provides the live channels and their programme
+//! to IPTV clients on the same device or in the local network.
+
+use std::{
+    collections::HashSet,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use adw::{gio, prelude::*};
+use reqwest::{StatusCode, Url};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, info};
+
+use crate::{http, utils::main_context};
+
+use super::{
+    live_guide, live_playlist_entries,
+    m3u::{self, PlaylistEntry},
+};
+
+/// Clients that don't send their request within this time are disconnected.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+/// Limits the hosts that are added by the HLS playlists of the proxied streams.
+const MAX_PROXY_HOSTS: usize = 256;
+
+#[derive(Clone, Debug)]
+struct Server {
+    proxy_streams: bool,
+    /// Hosts that the proxy is allowed to connect to, so it can't be abused as an open proxy.
+    ///
+    /// Hosts are never removed, so streams that are already playing keep working when another
+    /// client requests the playlist.
+    proxy_hosts: Arc<Mutex<HashSet<String>>>,
+    /// The entries of the last requested playlist, so their logos aren't exported for every request.
+    entries: Arc<Mutex<Option<Vec<PlaylistEntry>>>>,
+}
+
+/// Serves `/playlist.m3u` and `/epg.xml` on the given port until an error occurs.
+///
+/// Only connections from the same device are accepted unless `local_network` is set.
+pub async fn serve(port: u16, proxy_streams: bool, local_network: bool) -> eyre::Result<()> {
+    let ip = if local_network {
+        Ipv4Addr::UNSPECIFIED
+    } else {
+        Ipv4Addr::LOCALHOST
+    };
+    let address = SocketAddr::from((ip, port));
+    let listener = TcpListener::bind(address).await?;
+    info!("IPTV server listening on http://{address}");
+
+    let server = Server {
+        proxy_streams,
+        proxy_hosts: Default::default(),
+        entries: Default::default(),
+    };
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let server = server.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = server.handle_connection(stream).await {
+                debug!("failed to handle request from {peer}: {e:?}");
+            }
+        });
+    }
+}
+
+impl Server {
+    async fn handle_connection(&self, mut stream: TcpStream) -> eyre::Result<()> {
+        // the address the client connected to, which is also reachable for other devices if the server
+        // listens on all interfaces
+        let local_address = stream.local_addr()?;
+        let (reader, mut writer) = stream.split();
+        let mut reader = BufReader::new(reader);
+
+        let request_line =
+            match tokio::time::timeout(REQUEST_TIMEOUT, read_request_line(&mut reader)).await {
+                Ok(Ok(Some(request_line))) => request_line,
+                Ok(Ok(None)) => {
+                    return respond(
+                        &mut writer,
+                        StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                        "text/plain",
+                        b"",
+                    )
+                    .await
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => {
+                    return respond(&mut writer, StatusCode::REQUEST_TIMEOUT, "text/plain", b"")
+                        .await
+                }
+            };
+
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return respond(&mut writer, StatusCode::BAD_REQUEST, "text/plain", b"").await;
+        };
+        if method != "GET" {
+            return respond(
+                &mut writer,
+                StatusCode::METHOD_NOT_ALLOWED,
+                "text/plain",
+                b"",
+            )
+            .await;
+        }
+
+        // absolute URLs in the responses need to point to the address used by the client
+        let base = Url::parse(&format!("http://{local_address}/"))?;
+        let url = base.join(target)?;
+
+        match url.path() {
+            "/playlist.m3u" => {
+                let playlist = self.playlist(&base).await?;
+                respond(
+                    &mut writer,
+                    StatusCode::OK,
+                    "audio/x-mpegurl",
+                    playlist.as_bytes(),
+                )
+                .await
+            }
+            "/epg.xml" => {
+                let entries = self.entries(false).await?;
+                let guide = main_context(|| async move { live_guide(&entries).await }).await?;
+                respond(
+                    &mut writer,
+                    StatusCode::OK,
+                    "application/xml",
+                    guide.as_bytes(),
+                )
+                .await
+            }
+            "/proxy" if self.proxy_streams => {
+                match url.query_pairs().find(|(key, _)| key == "url") {
+                    Some((_, target)) => self.proxy(&mut writer, &base, &target).await,
+                    None => respond(&mut writer, StatusCode::BAD_REQUEST, "text/plain", b"").await,
+                }
+            }
+            path => match path.strip_prefix("/logos/") {
+                Some(channel_id) => self.logo(&mut writer, channel_id.to_owned()).await,
+                None => respond(&mut writer, StatusCode::NOT_FOUND, "text/plain", b"").await,
+            },
+        }
+    }
+
+    /// Returns the playlist entries, which are only loaded again if `reload` is set or they haven't
+    /// been loaded yet.
+    async fn entries(&self, reload: bool) -> eyre::Result<Vec<PlaylistEntry>> {
+        if !reload {
+            if let Some(entries) = self.entries.lock().unwrap().clone() {
+                return Ok(entries);
+            }
+        }
+
+        let entries = main_context(live_playlist_entries).await?;
+        self.entries.lock().unwrap().replace(entries.clone());
+
+        Ok(entries)
+    }
+
+    async fn playlist(&self, base: &Url) -> eyre::Result<String> {
+        let mut entries = self.entries(true).await?;
+
+        for entry in &mut entries {
+            // local file paths are useless for other devices
+            if entry.tvg_logo.is_some() {
+                entry.tvg_logo = Some(base.join(&format!("logos/{}", entry.tvg_id))?.to_string());
+            }
+            if self.proxy_streams {
+                self.allow_proxy_host(&entry.url);
+                entry.url = proxied_url(base, &entry.url);
+            }
+        }
+
+        Ok(m3u::write_playlist(&entries))
+    }
+
+    async fn logo(
+        &self,
+        writer: &mut (impl AsyncWrite + Unpin),
+        channel_id: String,
+    ) -> eyre::Result<()> {
+        let Some(path) = self
+            .entries(false)
+            .await?
+            .into_iter()
+            .find(|entry| entry.tvg_id == channel_id)
+            .and_then(|entry| entry.tvg_logo)
+            .and_then(|uri| gio::File::for_uri(&uri).path())
+        else {
+            return respond(writer, StatusCode::NOT_FOUND, "text/plain", b"").await;
+        };
+        let content_type = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("svg") => "image/svg+xml",
+            Some("png") => "image/png",
+            Some("jpg" | "jpeg") => "image/jpeg",
+            Some("webp") => "image/webp",
+            Some("gif") => "image/gif",
+            _ => "application/octet-stream",
+        };
+
+        respond(
+            writer,
+            StatusCode::OK,
+            content_type,
+            &tokio::fs::read(path).await?,
+        )
+        .await
+    }
+
+    async fn proxy(
+        &self,
+        writer: &mut (impl AsyncWrite + Unpin),
+        base: &Url,
+        target: &str,
+    ) -> eyre::Result<()> {
+        let target = Url::parse(target)?;
+        if !target
+            .host_str()
+            .is_some_and(|host| self.proxy_hosts.lock().unwrap().contains(host))
+        {
+            return respond(writer, StatusCode::FORBIDDEN, "text/plain", b"").await;
+        }
+
+        let mut response = http::client().get(target).send().await?;
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_owned();
+
+        if content_type.contains("mpegurl") || response.url().path().ends_with(".m3u8") {
+            // HLS playlists reference further playlists and segments that must be proxied as well
+            let playlist_url = response.url().clone();
+            let playlist = self.rewrite_hls_playlist(&response.text().await?, &playlist_url, base);
+
+            respond(writer, status, &content_type, playlist.as_bytes()).await
+        } else {
+            let mut head = format!(
+                "HTTP/1.1 {} {}\r\nContent-Type: {content_type}\r\nConnection: close\r\n",
+                status.as_u16(),
+                status.canonical_reason().unwrap_or_default()
+            );
+            if let Some(content_length) = response.content_length() {
+                head.push_str(&format!("Content-Length: {content_length}\r\n"));
+            }
+            head.push_str("\r\n");
+            writer.write_all(head.as_bytes()).await?;
+
+            while let Some(chunk) = response.chunk().await? {
+                writer.write_all(&chunk).await?;
+            }
+
+            Ok(writer.flush().await?)
+        }
+    }
+
+    fn rewrite_hls_playlist(&self, playlist: &str, playlist_url: &Url, base: &Url) -> String {
+        let mut rewritten = String::with_capacity(playlist.len());
+
+        for line in playlist.lines() {
+            if line.starts_with('#') {
+                // e.g. `#EXT-X-MEDIA:TYPE=AUDIO,URI="audio.m3u8"`
+                match line.split_once("URI=\"").and_then(|(head, rest)| {
+                    let (uri, tail) = rest.split_once('"')?;
+                    Some((head, uri, tail))
+                }) {
+                    Some((head, uri, tail)) => match playlist_url.join(uri) {
+                        Ok(uri) => {
+                            self.allow_proxy_host(uri.as_str());
+                            rewritten.push_str(&format!(
+                                "{head}URI=\"{}\"{tail}",
+                                proxied_url(base, uri.as_str())
+                            ));
+                        }
+                        Err(_) => rewritten.push_str(line),
+                    },
+                    None => rewritten.push_str(line),
+                }
+            } else if line.trim().is_empty() {
+                rewritten.push_str(line);
+            } else {
+                match playlist_url.join(line.trim()) {
+                    Ok(uri) => {
+                        self.allow_proxy_host(uri.as_str());
+                        rewritten.push_str(&proxied_url(base, uri.as_str()));
+                    }
+                    Err(_) => rewritten.push_str(line),
+                }
+            }
+            rewritten.push('\n');
+        }
+
+        rewritten
+    }
+
+    fn allow_proxy_host(&self, url: &str) {
+        if let Some(host) = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+        {
+            let mut proxy_hosts = self.proxy_hosts.lock().unwrap();
+            if proxy_hosts.len() < MAX_PROXY_HOSTS {
+                proxy_hosts.insert(host);
+            }
+        }
+    }
+}
+
+/// Reads the request line and skips the headers, which aren't needed.
+///
+/// Returns `None` if the request exceeds [`MAX_LINE_LENGTH`] or [`MAX_HEADERS`].
+async fn read_request_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+) -> eyre::Result<Option<String>> {
+    let mut request_line = String::new();
+    if !read_line(reader, &mut request_line).await? {
+        return Ok(None);
+    }
+
+    for _ in 0..MAX_HEADERS {
+        let mut line = String::new();
+        if !read_line(reader, &mut line).await? {
+            return Ok(None);
+        }
+        if line.trim().is_empty() {
+            return Ok(Some(request_line));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads a line of at most [`MAX_LINE_LENGTH`] bytes and returns `false` if it is longer.
+async fn read_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    line: &mut String,
+) -> eyre::Result<bool> {
+    let len = (&mut *reader).take(MAX_LINE_LENGTH).read_line(line).await?;
+
+    Ok(len < MAX_LINE_LENGTH as usize || line.ends_with('\n'))
+}
+
+fn proxied_url(base: &Url, target: &str) -> String {
+    let mut url = base.join("proxy").expect("base URL should be valid");
+    url.query_pairs_mut().append_pair("url", target);
+    url.to_string()
+}
+
+async fn respond(
+    writer: &mut (impl AsyncWrite + Unpin),
+    status: StatusCode,
+    content_type: &str,
+    body: &[u8],
+) -> eyre::Result<()> {
+    writer
+        .write_all(
+            format!(
+                "HTTP/1.1 {} {}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status.as_u16(),
+                status.canonical_reason().unwrap_or_default(),
+                body.len()
+            )
+            .as_bytes(),
+        )
+        .await?;
+    writer.write_all(body).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
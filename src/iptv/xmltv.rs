@@ -0,0 +1,146 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fmt::Write;
+
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::{config::APP_NAME, zapp::Show};
+
+use super::m3u::PlaylistEntry;
+
+/// Serializes the programme of the given channels as an XMLTV document.
+///
+/// The channel IDs match the `tvg-id` attributes of the M3U playlist.
+pub fn write_guide(channels: &[PlaylistEntry], shows: &[Show]) -> String {
+    let mut guide = String::new();
+
+    writeln!(guide, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(guide, r#"<!DOCTYPE tv SYSTEM "xmltv.dtd">"#).unwrap();
+    writeln!(guide, r#"<tv generator-info-name="{}">"#, escape(APP_NAME)).unwrap();
+
+    for PlaylistEntry {
+        tvg_id,
+        tvg_name,
+        tvg_logo,
+        ..
+    } in channels
+    {
+        writeln!(guide, r#"  <channel id="{}">"#, escape(tvg_id)).unwrap();
+        writeln!(
+            guide,
+            "    <display-name>{}</display-name>",
+            escape(tvg_name)
+        )
+        .unwrap();
+        if let Some(tvg_logo) = tvg_logo {
+            writeln!(guide, r#"    <icon src="{}"/>"#, escape(tvg_logo)).unwrap();
+        }
+        writeln!(guide, "  </channel>").unwrap();
+    }
+
+    for Show {
+        title,
+        subtitle,
+        description,
+        channel,
+        start_time,
+        end_time,
+    } in shows
+    {
+        writeln!(
+            guide,
+            r#"  <programme start="{}" stop="{}" channel="{}">"#,
+            format_time(*start_time),
+            format_time(*end_time),
+            escape(channel.as_ref())
+        )
+        .unwrap();
+        writeln!(guide, "    <title>{}</title>", escape(title)).unwrap();
+        if let Some(subtitle) = subtitle {
+            writeln!(guide, "    <sub-title>{}</sub-title>", escape(subtitle)).unwrap();
+        }
+        if let Some(description) = description {
+            writeln!(guide, "    <desc>{}</desc>", escape(description)).unwrap();
+        }
+        writeln!(guide, "  </programme>").unwrap();
+    }
+
+    writeln!(guide, "</tv>").unwrap();
+
+    guide
+}
+
+// XMLTV uses the format `YYYYMMDDhhmmss +zzzz`
+fn format_time(t: OffsetDateTime) -> String {
+    let t = t.to_offset(UtcOffset::UTC);
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02} +0000",
+        t.year(),
+        u8::from(t.month()),
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::zapp::ChannelId;
+
+    use super::*;
+
+    #[test]
+    fn write() {
+        let channels = [PlaylistEntry {
+            tvg_id: "zdf".to_owned(),
+            tvg_name: "ZDF".to_owned(),
+            tvg_logo: Some("file:///tmp/zdf.svg?a=1&b=2".to_owned()),
+            group_title: None,
+            url: "https://example.org/zdf.m3u8".to_owned(),
+        }];
+        let start_time = OffsetDateTime::from_unix_timestamp(1_700_000_000)
+            .unwrap()
+            .to_offset(UtcOffset::from_hms(1, 0, 0).unwrap());
+        let shows = [Show {
+            title: "heute <journal>".to_owned(),
+            subtitle: None,
+            description: Some("Nachrichten & \"Wetter\"".to_owned()),
+            channel: ChannelId::from("zdf".to_owned()),
+            start_time,
+            end_time: start_time + time::Duration::minutes(30),
+        }];
+
+        let guide = write_guide(&channels, &shows);
+
+        assert!(guide.contains(r#"  <channel id="zdf">"#));
+        assert!(guide.contains("    <display-name>ZDF</display-name>"));
+        assert!(guide.contains(r#"    <icon src="file:///tmp/zdf.svg?a=1&amp;b=2"/>"#));
+        assert!(guide.contains(
+            r#"  <programme start="20231114221320 +0000" stop="20231114224320 +0000" channel="zdf">"#
+        ));
+        assert!(guide.contains("    <title>heute &lt;journal&gt;</title>"));
+        assert!(!guide.contains("<sub-title>"));
+        assert!(guide.contains("    <desc>Nachrichten &amp; &quot;Wetter&quot;</desc>"));
+        assert!(guide.trim_end().ends_with("</tv>"));
+    }
+}
@@ -3,20 +3,28 @@
 
 use std::{
     cell::{Cell, RefCell},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use adw::{glib, gtk, prelude::*, subclass::prelude::*};
+use adw::{gdk, glib, gtk, prelude::*, subclass::prelude::*};
+use gettextrs::gettext;
 
 use crate::{
     channel_icons::load_channel_icon,
+    deep_link::DeepLink,
+    hls, network,
     player::VideoInfo,
+    snapshot,
     utils::{spawn, tokio},
     TvApplication,
 };
 
 use super::channels::ChannelObject;
 
+/// Previews of visible cards are refreshed after this time.
+const PREVIEW_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const PREVIEW_WIDTH: i32 = 320;
+
 mod imp {
     use super::*;
 
@@ -36,9 +44,16 @@ mod imp {
         revealer: TemplateChild<gtk::Revealer>,
         #[template_child]
         play_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub(super) preview: TemplateChild<gtk::Picture>,
 
         #[property(get, set)]
         expanded: Cell<bool>,
+        /// Whether a recent frame of the stream is shown, which is used in the grid layout.
+        #[property(get, construct_only)]
+        show_preview: Cell<bool>,
+        pub(super) preview_updated: Cell<Option<Instant>>,
+        pub(super) capturing_preview: Cell<bool>,
 
         #[property(get, construct_only)]
         pub(super) channel: RefCell<Option<ChannelObject>>,
@@ -73,6 +88,21 @@ mod imp {
                     })
                     .await
             });
+            klass.install_action("card.copy-link", None, |slf, _, _| {
+                let channel = slf.channel().unwrap();
+                slf.clipboard().set(
+                    &DeepLink::Live {
+                        channel_id: channel.id(),
+                    }
+                    .to_uri(),
+                );
+
+                TvApplication::get()
+                    .window()
+                    .add_toast(adw::Toast::new(&gettext(
+                        "Copied Televido link to clipboard",
+                    )));
+            });
         }
 
         fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
@@ -99,6 +129,22 @@ mod imp {
                 revealer.set_visible(revealer.is_child_revealed())
             });
 
+            if self.show_preview.get() {
+                self.obj().add_css_class("card");
+                self.obj().connect_map(|slf| slf.refresh_preview());
+
+                let slf = self.downgrade();
+                spawn(async move {
+                    loop {
+                        glib::timeout_future_with_priority(glib::Priority::LOW, PREVIEW_INTERVAL)
+                            .await;
+
+                        let Some(slf) = slf.upgrade() else { break };
+                        slf.obj().refresh_preview();
+                    }
+                });
+            }
+
             // update progress bar every 10 seconds
             let slf = self.downgrade();
             spawn(async move {
@@ -135,3 +181,57 @@ glib::wrapper! {
     pub struct TvLiveCard(ObjectSubclass<imp::TvLiveCard>)
         @extends gtk::Widget, gtk::ListBoxRow;
 }
+
+impl TvLiveCard {
+    /// Captures a new preview if the card is visible and the current one is outdated.
+    ///
+    /// Previews aren't refreshed while the bandwidth saver is active, e.g. on metered connections.
+    fn refresh_preview(&self) {
+        let imp = self.imp();
+        if !self.show_preview() || !self.is_mapped() || network::bandwidth_saver() {
+            return;
+        }
+        if imp
+            .preview_updated
+            .get()
+            .is_some_and(|updated| updated.elapsed() < PREVIEW_INTERVAL)
+        {
+            return;
+        }
+        let Some(channel) = self.channel() else {
+            return;
+        };
+        if imp.capturing_preview.replace(true) {
+            return;
+        }
+
+        let slf = self.downgrade();
+        spawn(async move {
+            let preview = capture_preview(channel.stream_url()).await;
+
+            let Some(slf) = slf.upgrade() else { return };
+            let imp = slf.imp();
+            imp.capturing_preview.set(false);
+            imp.preview_updated.set(Some(Instant::now()));
+            match preview {
+                Ok(texture) => imp.preview.set_paintable(Some(&texture)),
+                Err(e) => tracing::debug!("failed to capture preview of {}: {e:?}", channel.id()),
+            }
+        });
+    }
+}
+
+async fn capture_preview(stream_url: String) -> eyre::Result<gdk::Texture> {
+    // the video variant with the lowest bandwidth is sufficient for a still frame
+    let uri = if stream_url.contains(".m3u8") {
+        let variants = hls::fetch_variants(stream_url.clone()).await?;
+        hls::lowest_video_variant(&variants)
+            .map(|variant| variant.uri.clone())
+            .unwrap_or(stream_url)
+    } else {
+        stream_url
+    };
+
+    let bytes = snapshot::take(&uri, PREVIEW_WIDTH).await?;
+    Ok(gdk::Texture::from_bytes(&bytes)?)
+}
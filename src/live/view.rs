@@ -8,8 +8,11 @@ use smart_default::SmartDefault;
 
 use crate::{
     application::TvApplication,
+    error,
+    iptv::is_custom_channel,
+    network,
     settings::TvSettings,
-    utils::{show_error, spawn, tokio},
+    utils::{spawn, tokio},
     zapp::{ChannelId, ChannelInfo, Show, ShowsResult},
 };
 
@@ -26,37 +29,38 @@ mod imp {
         #[template_child]
         spinner: TemplateChild<gtk::Spinner>,
         #[template_child]
+        layout_stack: TemplateChild<gtk::Stack>,
+        #[template_child]
         channels_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        channels_grid: TemplateChild<gtk::FlowBox>,
+        #[template_child]
+        error_view: TemplateChild<adw::StatusPage>,
+
+        /// Set once the channels have been loaded, which are kept while offline.
+        loaded: std::cell::Cell<bool>,
+        channels: std::cell::RefCell<Option<gio::ListStore>>,
     }
 
     impl TvLiveView {
         async fn load_channels(&self) -> eyre::Result<gio::ListStore> {
             let client = TvApplication::get().zapp();
-            let settings = TvSettings::get();
-            let visible_channels = settings.live_channels();
-
-            let live_channels = TvApplication::get().live_channels().await;
-
-            let live_channels: Vec<(ChannelId, ChannelInfo)> = if visible_channels.is_empty() {
-                live_channels
-                    .iter()
-                    .map(|(channel_id, channel_info)| (channel_id.clone(), channel_info.clone()))
-                    .collect()
-            } else {
-                visible_channels
-                    .into_iter()
-                    .filter_map(|channel_id| {
-                        let channel_info = live_channels.get(&channel_id)?;
-                        Some((channel_id, channel_info.clone()))
-                    })
-                    .collect()
-            };
+            let live_channels = TvApplication::get().visible_live_channels().await;
+            if let Some(e) = TvApplication::get().live_channels_error() {
+                return Err(e.into());
+            }
 
             let load_channels = tokio(async move {
                 let mut channels: Vec<(ChannelId, ChannelInfo, Option<Vec<Show>>)> =
                     Vec::with_capacity(live_channels.len());
 
                 for (channel_id, channel_info) in live_channels.iter() {
+                    // there is no programme information for imported channels
+                    if is_custom_channel(channel_id) {
+                        channels.push((channel_id.clone(), channel_info.clone(), None));
+                        continue;
+                    }
+
                     match client.shows(channel_id).await.wrap_err_with(|| {
                         eyre::Report::msg(
                             // translators: `{}` is replaced by the channel_id, e.g. `das_erste`
@@ -76,10 +80,14 @@ mod imp {
                 Ok::<_, eyre::Report>(channels)
             });
 
+            let application = TvApplication::get();
             let channel_objects = load_channels
                 .await?
                 .into_iter()
                 .map(|(channel_id, channel_info, shows)| {
+                    if let Some(shows) = &shows {
+                        application.cache_shows(channel_id.clone(), shows.clone());
+                    }
                     let channel = ChannelObject::new(
                         channel_id.as_ref(),
                         &channel_info.name,
@@ -109,21 +117,66 @@ mod imp {
             Ok(channel_objects)
         }
         pub(super) async fn reload(&self) {
+            if !network::is_online() {
+                if !self.loaded.get() {
+                    self.stack.set_visible_child_name("offline");
+                    self.spinner.set_spinning(false);
+                }
+                return;
+            }
+
             self.spinner.set_spinning(true);
             self.stack.set_visible_child_name("spinner");
 
+            // the channel list is loaded again if it failed before, e.g. while offline
+            let application = TvApplication::get();
+            if application.live_channels_error().is_some() {
+                application.live_channels().reload().await;
+            }
+
             match self.load_channels().await {
                 Ok(channels) => {
-                    self.channels_list.bind_model(Some(&channels), |channel| {
-                        glib::Object::builder::<TvLiveCard>()
-                            .property("channel", channel)
-                            .build()
-                            .upcast()
-                    });
+                    self.channels.replace(Some(channels));
+                    self.bind_channels();
                     self.stack.set_visible_child_name("channels");
                     self.spinner.set_spinning(false);
+                    self.loaded.set(true);
+                }
+                Err(e) => {
+                    error::show_on_status_page(
+                        &e.wrap_err(gettext("Failed to load livestream channels")),
+                        &self.error_view,
+                    );
+                    self.stack.set_visible_child_name("error");
+                    self.spinner.set_spinning(false);
                 }
-                Err(e) => show_error(e.wrap_err(gettext("Failed to load livestream channels"))),
+            }
+        }
+        /// Shows the channels as a list or, if previews are enabled, as a grid.
+        fn bind_channels(&self) {
+            let channels = self.channels.borrow();
+            let previews = TvSettings::get().live_previews();
+            let create_card = move |channel: &glib::Object| -> gtk::Widget {
+                glib::Object::builder::<TvLiveCard>()
+                    .property("channel", channel)
+                    .property("show-preview", previews)
+                    .build()
+                    .upcast()
+            };
+
+            // only one layout is bound at a time, so previews aren't captured for hidden cards
+            if previews {
+                self.channels_list
+                    .bind_model(None::<&gio::ListModel>, create_card);
+                self.channels_grid
+                    .bind_model(channels.as_ref(), create_card);
+                self.layout_stack.set_visible_child_name("grid");
+            } else {
+                self.channels_grid
+                    .bind_model(None::<&gio::ListModel>, create_card);
+                self.channels_list
+                    .bind_model(channels.as_ref(), create_card);
+                self.layout_stack.set_visible_child_name("list");
             }
         }
     }
@@ -155,6 +208,18 @@ mod imp {
                     .expect("invalid ListBoxRow type");
                 row.set_expanded(!row.expanded())
             });
+            self.channels_grid.connect_child_activated(|_, child| {
+                let card = child
+                    .child()
+                    .and_downcast::<TvLiveCard>()
+                    .expect("invalid FlowBoxChild type");
+                card.set_expanded(!card.expanded())
+            });
+            settings.connect_live_previews_changed(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |_| slf.bind_channels()
+            ));
 
             let slf = self.to_owned();
             spawn(async move { slf.reload().await });
@@ -166,6 +231,26 @@ mod imp {
                     spawn(async move { slf.reload().await });
                 }
             ));
+            settings.connect_custom_channels_changed(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |_| {
+                    spawn(async move { slf.reload().await });
+                }
+            ));
+            network::connect_online_changed(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |online| {
+                    let failed = matches!(
+                        slf.stack.visible_child_name().as_deref(),
+                        Some("offline" | "error")
+                    );
+                    if online && failed {
+                        spawn(async move { slf.reload().await });
+                    }
+                }
+            ));
         }
     }
     impl WidgetImpl for TvLiveView {}
@@ -5,13 +5,24 @@
 
 mod application;
 mod channel_icons;
+mod channel_registry;
+mod cli;
 mod config;
+mod deep_link;
+mod error;
+mod hls;
+mod http;
+mod iptv;
 mod launcher;
 mod live;
 mod mediathek;
+mod network;
 mod player;
 mod preferences;
+mod remote_control;
+mod search_provider;
 mod settings;
+mod snapshot;
 mod utils;
 mod window;
 mod zapp;
@@ -45,6 +56,7 @@ fn main() -> glib::ExitCode {
     glib::setenv("CLAPPER_USE_PLAYBIN3", "1", false)
         .expect("failed to set CLAPPER_USE_PLAYBIN3 environment variable");
     clapper::init().expect("failed to initialize libclapper");
+    http::setup_gstreamer_proxy();
 
     TvApplication::new().run()
 }
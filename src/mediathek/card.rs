@@ -9,12 +9,26 @@ use gettextrs::gettext;
 use crate::{
     application::TvApplication,
     channel_icons::load_channel_icon,
+    channel_registry::ChannelRegistry,
+    deep_link::DeepLink,
+    network,
     player::VideoInfo,
     settings::{TvSettings, VideoQuality},
-    utils::{show_error, spawn},
+    utils::{show_error, spawn, TaskLimiter},
 };
 
-use super::shows::ShowObject;
+use super::{
+    probe::{Availability, VideoFileInfo},
+    shows::ShowObject,
+    thumbnail,
+};
+
+thread_local! {
+    /// Probing sends a request for each video file, so only a few results are probed at a time.
+    static PROBE_LIMITER: TaskLimiter = TaskLimiter::new(4);
+    /// Thumbnails might require downloading a website or decoding a video frame.
+    static THUMBNAIL_LIMITER: TaskLimiter = TaskLimiter::new(2);
+}
 
 mod imp {
     use super::*;
@@ -23,10 +37,26 @@ mod imp {
     #[template(file = "src/mediathek/card.blp")]
     #[properties(wrapper_type=super::TvMediathekCard)]
     pub struct TvMediathekCard {
+        #[template_child]
+        pub(super) thumbnail: TemplateChild<gtk::Picture>,
         #[template_child]
         icon: TemplateChild<gtk::Image>,
         #[template_child]
         revealer: TemplateChild<gtk::Revealer>,
+        #[template_child]
+        pub(super) play_button: TemplateChild<adw::SplitButton>,
+        #[template_child]
+        pub(super) copy_url_button: TemplateChild<gtk::MenuButton>,
+
+        /// Details about the video files, which are loaded when the card is shown for the first time.
+        pub(super) files: RefCell<Vec<(VideoQuality, VideoFileInfo)>>,
+        pub(super) probing: Cell<bool>,
+        pub(super) loading_thumbnail: Cell<bool>,
+        #[property(get)]
+        pub(super) geo_restricted: Cell<bool>,
+        /// Set if the video files have been removed.
+        #[property(get)]
+        pub(super) unavailable: Cell<bool>,
 
         #[property(get, construct_only)]
         show: RefCell<Option<ShowObject>>,
@@ -36,11 +66,14 @@ mod imp {
     }
     impl TvMediathekCard {
         fn set_icon(&self) {
-            load_channel_icon(
-                self.obj().show().map(|c| c.channel()).as_deref(),
-                &self.icon,
-                64,
-            )
+            let channel = self.obj().show().map(|c| c.channel());
+
+            load_channel_icon(channel.as_deref(), &self.icon, 64);
+            self.icon.set_tooltip_text(
+                channel
+                    .map(|channel| ChannelRegistry::get().display_name(&channel))
+                    .as_deref(),
+            );
         }
     }
 
@@ -66,6 +99,19 @@ mod imp {
 
             self.obj().setup_actions();
             self.obj().connect_show_notify(|slf| slf.imp().set_icon());
+            self.obj().connect_show_notify(|slf| slf.update_menus());
+            let slf = self.obj();
+            let settings = TvSettings::get();
+            settings.connect_hide_geo_restricted_changed(glib::clone!(
+                #[weak]
+                slf,
+                move |_| slf.update_visibility()
+            ));
+            settings.connect_hide_unavailable_changed(glib::clone!(
+                #[weak]
+                slf,
+                move |_| slf.update_visibility()
+            ));
 
             self.revealer.connect_child_revealed_notify(|revealer| {
                 revealer.set_visible(revealer.is_child_revealed())
@@ -82,25 +128,180 @@ glib::wrapper! {
 }
 
 impl TvMediathekCard {
-    fn play(&self, quality: VideoQuality) {
+    fn video_info(&self, quality: VideoQuality) -> VideoInfo {
         let show = self
             .show()
             .expect("action must only be enabled if show is not None");
 
+        // the quality falls back to an available one, e.g. while the bandwidth saver is active
+        VideoInfo::mediathek_with_default_quality(
+            quality,
+            show.title(),
+            show.subtitle_url(),
+            show.video_url_high(),
+            show.video_url_medium(),
+            show.video_url_low(),
+            show.channel(),
+        )
+    }
+    /// Loads the details of the show, which is done when the card is scrolled into view.
+    pub fn load_details(&self) {
+        self.probe_files();
+        self.load_thumbnail();
+    }
+    /// Loads the resolution, size and availability of the video files.
+    fn probe_files(&self) {
+        let imp = self.imp();
+        if imp.probing.replace(true) {
+            return;
+        }
+        let Some(show) = self.show() else { return };
+        let slf = self.downgrade();
+
+        spawn(async move {
+            let limiter = PROBE_LIMITER.with(TaskLimiter::clone);
+            let files = limiter
+                .run(async {
+                    // the results might have changed while waiting
+                    slf.upgrade()?;
+
+                    let mut files = Vec::new();
+                    for quality in [VideoQuality::High, VideoQuality::Medium, VideoQuality::Low] {
+                        if let Some(url) = show.video_url(quality) {
+                            files.push((quality, VideoFileInfo::probe(url).await));
+                        }
+                    }
+                    Some(files)
+                })
+                .await;
+
+            if let Some((slf, files)) = slf.upgrade().zip(files) {
+                slf.imp().files.replace(files);
+                slf.update_menus();
+                slf.update_availability();
+            }
+        });
+    }
+    fn load_thumbnail(&self) {
+        if self.imp().loading_thumbnail.replace(true) {
+            return;
+        }
+        let Some(show) = self.show() else { return };
+        let slf = self.downgrade();
+
         spawn(async move {
-            TvApplication::get()
-                .play(VideoInfo::Mediathek {
-                    title: show.title(),
-                    preferred_quality: quality,
-                    subtitle_uri: show.subtitle_url(),
-                    uri_high: show.video_url_high(),
-                    uri_medium: show.video_url_medium(),
-                    uri_low: show.video_url_low(),
-                    channel_id: show.channel(),
+            let limiter = THUMBNAIL_LIMITER.with(TaskLimiter::clone);
+            let texture = limiter
+                .run(async {
+                    // the results might have changed while waiting
+                    slf.upgrade()?;
+                    thumbnail::load(&show).await
                 })
-                .await
+                .await;
+            let Some(texture) = texture else {
+                return;
+            };
+            if let Some(slf) = slf.upgrade() {
+                let thumbnail = &slf.imp().thumbnail;
+                thumbnail.set_paintable(Some(&texture));
+                thumbnail.set_visible(true);
+            }
         });
     }
+    fn update_availability(&self) {
+        let files = self.imp().files.borrow();
+        let availability = |a| files.iter().any(|(_, file)| file.availability == a);
+
+        // files that couldn't be probed are assumed to be geo-blocked or removed as well
+        let geo_restricted =
+            availability(Availability::GeoBlocked) && !availability(Availability::Available);
+        let unavailable = !files.is_empty()
+            && files
+                .iter()
+                .all(|(_, file)| file.availability == Availability::Unavailable);
+
+        let imp = self.imp();
+        if imp.geo_restricted.replace(geo_restricted) != geo_restricted {
+            self.notify_geo_restricted();
+        }
+        if imp.unavailable.replace(unavailable) != unavailable {
+            self.notify_unavailable();
+        }
+        self.update_visibility();
+    }
+    fn update_visibility(&self) {
+        let settings = TvSettings::get();
+        self.set_visible(
+            !((self.geo_restricted() && settings.hide_geo_restricted())
+                || (self.unavailable() && settings.hide_unavailable())),
+        );
+    }
+    fn update_menus(&self) {
+        let files = self.imp().files.borrow();
+        let file = |quality| {
+            files
+                .iter()
+                .find(|(q, _)| *q == quality)
+                .map(|(_, file)| file)
+        };
+
+        let qualities = [
+            (VideoQuality::High, gettext("High Quality")),
+            (VideoQuality::Medium, gettext("Medium Quality")),
+            (VideoQuality::Low, gettext("Low Quality")),
+        ]
+        .into_iter()
+        .filter(|(quality, _)| {
+            // some channels provide the same file for high and medium quality
+            !(*quality == VideoQuality::High
+                && file(VideoQuality::High)
+                    .zip(file(VideoQuality::Medium))
+                    .is_some_and(|(high, medium)| high.is_same_file(medium)))
+        })
+        .map(|(quality, fallback)| {
+            let label = file(quality)
+                .and_then(VideoFileInfo::label)
+                .unwrap_or(fallback);
+            (quality, label)
+        })
+        .collect::<Vec<_>>();
+
+        let play_menu = gio::Menu::new();
+        let copy_url_menu = gio::Menu::new();
+        for (quality, label) in &qualities {
+            play_menu.append(Some(label), Some(&format!("card.play-{quality}")));
+            copy_url_menu.append(Some(label), Some(&format!("card.copy-url-{quality}")));
+        }
+        copy_url_menu.append(Some(&gettext("Subtitles")), Some("card.copy-subtitles-url"));
+        let section = gio::Menu::new();
+        section.append(Some(&gettext("Televido Link")), Some("card.copy-link"));
+        copy_url_menu.append_section(None, &section);
+
+        let imp = self.imp();
+        imp.play_button.set_menu_model(Some(&play_menu));
+        imp.copy_url_button.set_menu_model(Some(&copy_url_menu));
+    }
+    /// The user's preferred quality for the channel of the show.
+    fn default_quality(&self) -> VideoQuality {
+        self.show()
+            .map(|show| VideoQuality::for_channel(&show.channel()))
+            .unwrap_or_else(VideoQuality::default_playback)
+    }
+    fn play(&self, quality: VideoQuality) {
+        let video_info = self.video_info(quality);
+
+        spawn(async move { TvApplication::get().play(video_info).await });
+    }
+    fn copy_link(&self) {
+        self.clipboard()
+            .set(&DeepLink::Play(self.video_info(self.default_quality())).to_uri());
+
+        TvApplication::get()
+            .window()
+            .add_toast(adw::Toast::new(&gettext(
+                "Copied Televido link to clipboard",
+            )));
+    }
     fn copy_video_url(&self, quality: VideoQuality) {
         self.clipboard().set(
             &self
@@ -167,21 +368,42 @@ impl TvMediathekCard {
             }};
         }
 
-        let play_default =
-            video_url_action!("play-default", play, VideoQuality::default_playback());
-        TvSettings::get().connect_default_playback_quality_changed(glib::clone!(
+        let play_default = gio::SimpleAction::new("play-default", None);
+        play_default.connect_activate(glib::clone!(
+            #[weak(rename_to = slf)]
+            self,
+            move |_, _| slf.play(slf.default_quality())
+        ));
+        let update_play_default = glib::clone!(
             #[weak(rename_to = slf)]
             self,
             #[weak]
             play_default,
-            move |_| {
+            move || {
                 play_default.set_enabled(
-                    slf.show()
-                        .and_then(|show| show.video_url(VideoQuality::default_playback()))
-                        .is_some(),
+                    slf.show().is_some() && slf.video_info(slf.default_quality()).uri().is_some(),
                 );
             }
+        );
+        self.connect_show_notify(glib::clone!(
+            #[strong]
+            update_play_default,
+            move |_| update_play_default()
+        ));
+        let settings = TvSettings::get();
+        settings.connect_default_playback_quality_changed(glib::clone!(
+            #[strong]
+            update_play_default,
+            move |_| update_play_default()
         ));
+        settings.connect_channel_playback_quality_changed(glib::clone!(
+            #[strong]
+            update_play_default,
+            move |_| update_play_default()
+        ));
+        network::connect_bandwidth_saver_changed(move |_| update_play_default());
+        actions.add_action(&play_default);
+
         video_url_action!("play-high", play, VideoQuality::High);
         video_url_action!("play-medium", play, VideoQuality::Medium);
         video_url_action!("play-low", play, VideoQuality::Low);
@@ -206,6 +428,25 @@ impl TvMediathekCard {
         ));
         actions.add_action(&copy_subtitles_url);
 
+        let copy_link = gio::SimpleAction::new("copy-link", None);
+        copy_link.connect_activate(glib::clone!(
+            #[weak(rename_to = slf)]
+            self,
+            move |_, _| slf.copy_link()
+        ));
+        self.connect_show_notify(glib::clone!(
+            #[weak]
+            copy_link,
+            move |slf| {
+                copy_link.set_enabled(slf.show().is_some_and(|show| {
+                    show.video_url_high().is_some()
+                        || show.video_url_medium().is_some()
+                        || show.video_url_low().is_some()
+                }));
+            }
+        ));
+        actions.add_action(&copy_link);
+
         let download = gio::SimpleAction::new("download", None);
         download.connect_activate(glib::clone!(
             #[weak(rename_to = slf)]
@@ -250,6 +491,36 @@ impl TvMediathekCard {
         ));
         actions.add_action(&open_website);
 
+        let search_website = gio::SimpleAction::new("search-website", None);
+        search_website.connect_activate(glib::clone!(
+            #[weak(rename_to = slf)]
+            self,
+            move |_, _| spawn(async move {
+                let url = slf
+                    .show()
+                    .and_then(|show| show.website_search_url())
+                    .expect("action must only be enabled if url is not None");
+                if let Err(e) = gtk::UriLauncher::new(&url)
+                    .launch_future(slf.root().and_downcast_ref::<adw::Window>())
+                    .await
+                {
+                    show_error(
+                        eyre::Report::msg(e.to_string())
+                            .wrap_err(gettext("Failed to open website in browser")),
+                    );
+                }
+            })
+        ));
+        self.connect_show_notify(glib::clone!(
+            #[weak]
+            search_website,
+            move |slf| {
+                search_website
+                    .set_enabled(slf.show().and_then(|show| show.website_url()).is_some());
+            }
+        ));
+        actions.add_action(&search_website);
+
         self.insert_action_group("card", Some(&actions));
     }
 }
@@ -0,0 +1,208 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Client-side filters for Mediathek search results.
+
+use std::{collections::HashSet, fmt::Display, str::FromStr, time::Duration};
+
+use mediathekviewweb::models::Item;
+
+use crate::settings::TvSettings;
+
+/// How versions with audio description or sign language are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VersionFilter {
+    #[default]
+    Show,
+    Hide,
+    /// Hides the regular version if the special version of the same show is available.
+    Prefer,
+    Only,
+}
+
+impl VersionFilter {
+    pub const ALL: [Self; 4] = [Self::Show, Self::Hide, Self::Prefer, Self::Only];
+}
+
+impl FromStr for VersionFilter {
+    type Err = eyre::Report;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "show" => Ok(Self::Show),
+            "hide" => Ok(Self::Hide),
+            "prefer" => Ok(Self::Prefer),
+            "only" => Ok(Self::Only),
+            _ => Err(eyre::eyre!("invalid value for version filter: \"{s}\"")),
+        }
+    }
+}
+impl Display for VersionFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Show => "show",
+            Self::Hide => "hide",
+            Self::Prefer => "prefer",
+            Self::Only => "only",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Version {
+    AudioDescription,
+    SignLanguage,
+}
+
+impl Version {
+    /// Lowercase parts of the title that mark the version, longest first.
+    fn markers(self) -> &'static [&'static str] {
+        match self {
+            Self::AudioDescription => &["mit audiodeskription", "audiodeskription", "hörfassung"],
+            Self::SignLanguage => &["mit gebärdensprache", "gebärdensprache", "(dgs)", "(ögs)"],
+        }
+    }
+    fn matches(self, item: &Item) -> bool {
+        let title = item.title.to_lowercase();
+        self.markers().iter().any(|marker| title.contains(marker))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ContentFilter {
+    min_duration: Duration,
+    audio_description: VersionFilter,
+    sign_language: VersionFilter,
+    blocked_topics: HashSet<String>,
+    blocked_channels: HashSet<String>,
+}
+
+impl ContentFilter {
+    pub fn from_settings() -> Self {
+        let settings = TvSettings::get();
+
+        Self {
+            min_duration: Duration::from_secs(60 * u64::from(settings.min_duration())),
+            audio_description: settings
+                .audio_description_filter()
+                .parse()
+                .unwrap_or_default(),
+            sign_language: settings.sign_language_filter().parse().unwrap_or_default(),
+            blocked_topics: settings
+                .blocked_topics()
+                .iter()
+                .map(|topic| topic.to_lowercase())
+                .collect(),
+            blocked_channels: settings
+                .blocked_channels()
+                .iter()
+                .map(|channel| channel.to_lowercase())
+                .collect(),
+        }
+    }
+    /// Removes all items that are excluded by the filter.
+    pub fn apply(&self, items: Vec<Item>) -> Vec<Item> {
+        let items = items
+            .into_iter()
+            .filter(|item| self.is_allowed(item))
+            .collect::<Vec<_>>();
+
+        let items = prefer_version(items, Version::AudioDescription, self.audio_description);
+        prefer_version(items, Version::SignLanguage, self.sign_language)
+    }
+    fn is_allowed(&self, item: &Item) -> bool {
+        if item
+            .duration
+            .is_some_and(|duration| duration < self.min_duration)
+        {
+            return false;
+        }
+        if self.blocked_topics.contains(&item.topic.to_lowercase())
+            || self.blocked_channels.contains(&item.channel.to_lowercase())
+        {
+            return false;
+        }
+
+        [
+            (Version::AudioDescription, self.audio_description),
+            (Version::SignLanguage, self.sign_language),
+        ]
+        .into_iter()
+        .all(|(version, filter)| match filter {
+            VersionFilter::Show | VersionFilter::Prefer => true,
+            VersionFilter::Hide => !version.matches(item),
+            VersionFilter::Only => version.matches(item),
+        })
+    }
+}
+
+/// Removes regular versions of shows if the given version is also part of the results.
+fn prefer_version(items: Vec<Item>, version: Version, filter: VersionFilter) -> Vec<Item> {
+    if filter != VersionFilter::Prefer {
+        return items;
+    }
+
+    let preferred = items
+        .iter()
+        .filter(|item| version.matches(item))
+        .map(|item| show_key(item, version))
+        .collect::<HashSet<_>>();
+
+    items
+        .into_iter()
+        .filter(|item| version.matches(item) || !preferred.contains(&show_key(item, version)))
+        .collect()
+}
+
+/// Identifies a show independent of the markers of the given version.
+fn show_key(item: &Item, version: Version) -> (String, String, String) {
+    let mut title = item.title.to_lowercase();
+    for marker in version.markers() {
+        title = title.replace(marker, "");
+    }
+    let title = title.chars().filter(|c| c.is_alphanumeric()).collect();
+
+    (item.channel.clone(), item.topic.clone(), title)
+}
+
+/// A list of topics or channels that are hidden from the search results.
+#[derive(Clone, Copy, Debug)]
+pub enum Blocklist {
+    Topics,
+    Channels,
+}
+
+impl Blocklist {
+    pub fn entries(self) -> Vec<String> {
+        let settings = TvSettings::get();
+        match self {
+            Self::Topics => settings.blocked_topics(),
+            Self::Channels => settings.blocked_channels(),
+        }
+    }
+    fn set_entries(self, entries: &[String]) {
+        let settings = TvSettings::get();
+        let entries = entries.iter().map(String::as_str).collect::<Vec<_>>();
+        match self {
+            Self::Topics => settings.set_blocked_topics(&entries),
+            Self::Channels => settings.set_blocked_channels(&entries),
+        }
+    }
+    pub fn add(self, entry: &str) {
+        let entry = entry.trim();
+        let mut entries = self.entries();
+        if entry.is_empty()
+            || entries
+                .iter()
+                .any(|e| e.to_lowercase() == entry.to_lowercase())
+        {
+            return;
+        }
+        entries.push(entry.to_owned());
+        self.set_entries(&entries);
+    }
+    pub fn remove(self, entry: &str) {
+        let mut entries = self.entries();
+        entries.retain(|e| e != entry);
+        self.set_entries(&entries);
+    }
+}
@@ -2,7 +2,45 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 mod card;
+mod filter;
+mod probe;
 mod shows;
+mod thumbnail;
 mod view;
 
-pub use self::view::TvMediathekView;
+pub use self::{
+    filter::{Blocklist, VersionFilter},
+    probe::{Availability, VideoFileInfo},
+    shows::ShowObject,
+    view::TvMediathekView,
+};
+
+/// The channels that are available in MediathekViewWeb.
+pub const CHANNELS: &[&str] = &[
+    "3Sat",
+    "ARD",
+    "ARTE.DE",
+    "ARTE.EN",
+    "ARTE.ES",
+    "ARTE.FR",
+    "ARTE.IT",
+    "ARTE.PL",
+    "BR",
+    "DW",
+    "Funk.net",
+    "HR",
+    "KiKA",
+    "MDR",
+    "NDR",
+    "ORF",
+    "PHOENIX",
+    "Radio Bremen TV",
+    "RBB",
+    "rbtv",
+    "SR",
+    "SRF",
+    "SWR",
+    "WDR",
+    "ZDF",
+    "ZDF-tivi",
+];
@@ -0,0 +1,140 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Details about the video files of a show that aren't part of the search results.
+
+use adw::glib;
+use reqwest::{header::CONTENT_LENGTH, StatusCode, Url};
+
+use crate::{
+    http,
+    utils::{is_same_domain, tokio},
+};
+
+/// Placeholder videos that broadcasters redirect to instead of responding with `403 Forbidden`,
+/// given as the domain and a part of the path.
+const GEO_BLOCK_PLACEHOLDERS: &[(&str, &str)] = &[
+    // ORF
+    ("apa.at", "geoprotection"),
+    ("orf.at", "geoprotection"),
+    // SRF
+    ("srf.ch", "geoblock"),
+    ("srgssr.ch", "geoblock"),
+    // ARD and ZDF
+    ("akamaihd.net", "geoblock"),
+    ("zdf.de", "geoblock"),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Availability {
+    /// The file couldn't be probed, e.g. because the server wasn't reachable.
+    #[default]
+    Unknown,
+    Available,
+    /// The file is only provided in some countries.
+    GeoBlocked,
+    /// The file has been removed, e.g. because the broadcaster's license has expired.
+    Unavailable,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VideoFileInfo {
+    /// The URL after following redirects.
+    pub url: String,
+    pub size: Option<u64>,
+    pub height: Option<u32>,
+    pub availability: Availability,
+}
+
+impl VideoFileInfo {
+    /// Sends a HEAD request to find out the size of the file.
+    pub async fn probe(url: String) -> Self {
+        let height = height_from_url(&url);
+
+        tokio(async move {
+            match http::client().head(&url).send().await {
+                Ok(response) => Self {
+                    url: response.url().to_string(),
+                    availability: availability(response.status(), response.url()),
+                    // `Response::content_length` is always zero for HEAD requests
+                    size: response
+                        .headers()
+                        .get(CONTENT_LENGTH)
+                        .and_then(|value| value.to_str().ok()?.parse().ok()),
+                    height: height.or_else(|| height_from_url(response.url().as_str())),
+                },
+                Err(e) => {
+                    tracing::debug!("failed to probe video file {url}: {e}");
+                    Self {
+                        url,
+                        size: None,
+                        height,
+                        availability: Availability::Unknown,
+                    }
+                }
+            }
+        })
+        .await
+    }
+    /// Whether both infos most likely describe the same file.
+    pub fn is_same_file(&self, other: &Self) -> bool {
+        self.url == other.url
+            || (self.size.is_some() && self.size == other.size && self.height == other.height)
+    }
+    /// A label like `1080p · 1.2 GB` or `None` if nothing is known about the file.
+    pub fn label(&self) -> Option<String> {
+        let parts = [
+            self.height.map(|height| format!("{height}p")),
+            self.size.map(|size| glib::format_size(size).to_string()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        (!parts.is_empty()).then(|| parts.join(" · "))
+    }
+}
+
+fn availability(status: StatusCode, url: &Url) -> Availability {
+    if status == StatusCode::FORBIDDEN || status == StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS {
+        return Availability::GeoBlocked;
+    }
+
+    let host = url.host_str().unwrap_or_default();
+    let path = url.path().to_lowercase();
+    if GEO_BLOCK_PLACEHOLDERS
+        .iter()
+        .any(|(domain, marker)| is_same_domain(host, domain) && path.contains(marker))
+    {
+        return Availability::GeoBlocked;
+    }
+
+    if status.is_success() {
+        Availability::Available
+    } else if status == StatusCode::NOT_FOUND || status == StatusCode::GONE {
+        Availability::Unavailable
+    } else {
+        Availability::Unknown
+    }
+}
+
+/// Reads the resolution from URLs like `…_1920x1080_….mp4` or `…/720p/….mp4`.
+fn height_from_url(url: &str) -> Option<u32> {
+    let path = url.split(['?', '#']).next()?;
+    let tokens = path
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|token| !token.is_empty());
+
+    tokens.rev().find_map(|token| {
+        let parse = |s: &str| {
+            s.parse::<u32>()
+                .ok()
+                .filter(|height| (144..=4320).contains(height))
+        };
+
+        match token.split_once('x') {
+            Some((width, height)) if width.parse::<u32>().is_ok() => parse(height),
+            _ => token.strip_suffix('p').and_then(parse),
+        }
+    })
+}
@@ -1,16 +1,25 @@
 // SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::cell::OnceCell;
+use std::{cell::OnceCell, time::Duration};
 
 use adw::{glib, prelude::*, subclass::prelude::*};
 use mediathekviewweb::models::Item;
 
 use crate::{
     settings::VideoQuality,
-    utils::{format_duration, format_timestamp_full},
+    utils::{format_duration, format_timestamp_full, is_same_domain},
 };
 
+/// Search pages of the broadcasters' websites, given as the domain and the URL that the query is appended to.
+const WEBSITE_SEARCH_URLS: &[(&str, &str)] = &[
+    ("ardmediathek.de", "https://www.ardmediathek.de/suche/"),
+    ("zdf.de", "https://www.zdf.de/suche?q="),
+    ("3sat.de", "https://www.3sat.de/suche?q="),
+    ("arte.tv", "https://www.arte.tv/de/search/?q="),
+    ("srf.ch", "https://www.srf.ch/play/tv/suche?query="),
+];
+
 mod imp {
     use super::*;
 
@@ -46,6 +55,16 @@ mod imp {
             type = Option<glib::GString>,
             get = |show: &ShowObject| format_timestamp_full(show.inner.get().unwrap().timestamp),
         )]
+        #[property(
+            name = "timestamp",
+            type = i64,
+            get = |show: &ShowObject| show.inner.get().unwrap().timestamp,
+        )]
+        #[property(
+            name = "duration-seconds",
+            type = u64,
+            get = |show: &ShowObject| show.inner.get().unwrap().duration.as_ref().map(Duration::as_secs).unwrap_or_default(),
+        )]
         #[property(
             name = "duration",
             type = String,
@@ -121,4 +140,27 @@ impl ShowObject {
             VideoQuality::Low => self.video_url_low(),
         }
     }
+    /// Searches for the show on the broadcaster's website, which might still provide it.
+    ///
+    /// Falls back to the website of the show if the search page of the broadcaster isn't known.
+    pub fn website_search_url(&self) -> Option<String> {
+        let website_url = self.website_url()?;
+        let host = glib::Uri::parse(&website_url, glib::UriFlags::NONE)
+            .ok()
+            .and_then(|uri| uri.host())
+            .unwrap_or_default();
+
+        Some(
+            WEBSITE_SEARCH_URLS
+                .iter()
+                .find(|(domain, _)| is_same_domain(&host, domain))
+                .map(|(_, search_url)| {
+                    format!(
+                        "{search_url}{}",
+                        glib::Uri::escape_string(&self.title(), None, false)
+                    )
+                })
+                .unwrap_or(website_url),
+        )
+    }
 }
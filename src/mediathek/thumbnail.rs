@@ -0,0 +1,182 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Thumbnails of Mediathek results, which are cached on disk.
+//!
+//! The preview image of the show's website is preferred, otherwise a frame is taken from the video.
+
+use std::path::PathBuf;
+
+use adw::{gdk, glib};
+use reqwest::{header::CONTENT_LENGTH, Url};
+
+use crate::{http, network, settings::VideoQuality, snapshot, utils::tokio};
+
+use super::shows::ShowObject;
+
+/// Older thumbnails are removed when the cache exceeds this size.
+const MAX_CACHE_SIZE: u64 = 64 * 1024 * 1024;
+/// Larger preview images are ignored.
+const MAX_IMAGE_SIZE: u64 = 2 * 1024 * 1024;
+/// Only the beginning of a website is searched for the preview image.
+const MAX_HTML_SIZE: usize = 1024 * 1024;
+const FRAME_WIDTH: i32 = 320;
+
+/// Loads the thumbnail of a show from the cache or the network.
+///
+/// Thumbnails aren't downloaded while the bandwidth saver is active.
+pub async fn load(show: &ShowObject) -> Option<gdk::Texture> {
+    let key = show
+        .website_url()
+        .or_else(|| video_url(show))
+        .and_then(|url| glib::compute_checksum_for_string(glib::ChecksumType::Sha256, url))?;
+    let path = cache_dir().join(key.as_str());
+
+    if let Ok(texture) = gdk::Texture::from_filename(&path) {
+        return Some(texture);
+    }
+    if network::bandwidth_saver() {
+        return None;
+    }
+
+    let bytes = match show.website_url() {
+        Some(website_url) => match tokio(preview_image(website_url)).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::debug!("failed to load preview image: {e:?}");
+                None
+            }
+        },
+        None => None,
+    };
+    let bytes = match bytes {
+        Some(bytes) => bytes,
+        None => match snapshot::take(&video_url(show)?, FRAME_WIDTH).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::debug!("failed to take a frame from the video: {e:?}");
+                return None;
+            }
+        },
+    };
+
+    let texture = match gdk::Texture::from_bytes(&bytes) {
+        Ok(texture) => texture,
+        Err(e) => {
+            tracing::debug!("failed to decode thumbnail: {e}");
+            return None;
+        }
+    };
+    if let Err(e) = tokio(save(path, bytes.to_vec())).await {
+        tracing::warn!("failed to cache thumbnail: {e:?}");
+    }
+
+    Some(texture)
+}
+
+fn cache_dir() -> PathBuf {
+    glib::user_cache_dir().join("televido/thumbnails")
+}
+
+/// The lowest quality is sufficient for a thumbnail.
+fn video_url(show: &ShowObject) -> Option<String> {
+    [VideoQuality::Low, VideoQuality::Medium, VideoQuality::High]
+        .into_iter()
+        .find_map(|quality| show.video_url(quality))
+}
+
+async fn save(path: PathBuf, bytes: Vec<u8>) -> eyre::Result<()> {
+    let cache_dir = cache_dir();
+    tokio::fs::create_dir_all(&cache_dir).await?;
+    tokio::fs::write(&path, bytes).await?;
+
+    // the most recently created thumbnails are kept
+    let mut files = Vec::new();
+    let mut entries = tokio::fs::read_dir(&cache_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        files.push((metadata.modified()?, metadata.len(), entry.path()));
+    }
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let mut size = 0;
+    for (_, len, path) in files {
+        size += len;
+        if size > MAX_CACHE_SIZE {
+            tokio::fs::remove_file(path).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Downloads the image from the `og:image` meta tag of a website.
+async fn preview_image(website_url: String) -> eyre::Result<Option<glib::Bytes>> {
+    let mut response = http::client()
+        .get(&website_url)
+        .send()
+        .await?
+        .error_for_status()?;
+    let base = response.url().clone();
+
+    // the meta tags are part of the document's head
+    let mut html = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        html.extend_from_slice(&chunk);
+        if html.len() >= MAX_HTML_SIZE {
+            html.truncate(MAX_HTML_SIZE);
+            break;
+        }
+    }
+    let html = String::from_utf8_lossy(&html);
+
+    let Some(image_url) = og_image(&html, &base) else {
+        return Ok(None);
+    };
+
+    let mut response = http::client()
+        .get(image_url)
+        .send()
+        .await?
+        .error_for_status()?;
+    let too_large = |size: u64| size > MAX_IMAGE_SIZE;
+    if response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok())
+        .is_some_and(too_large)
+    {
+        return Ok(None);
+    }
+
+    // the content length is optional, so the download is aborted once the image is too large
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        if too_large(bytes.len() as u64) {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(glib::Bytes::from_owned(bytes)))
+}
+
+/// Finds the URL of `<meta property="og:image" content="…">`.
+fn og_image(html: &str, base: &Url) -> Option<Url> {
+    fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+        ['"', '\''].into_iter().find_map(|quote| {
+            let start = tag.find(&format!("{name}={quote}"))? + name.len() + 2;
+            let len = tag[start..].find(quote)?;
+            Some(&tag[start..start + len])
+        })
+    }
+
+    html.split('<')
+        .filter(|tag| tag.starts_with("meta"))
+        .filter_map(|tag| tag.split('>').next())
+        .find(|tag| {
+            attribute(tag, "property").or_else(|| attribute(tag, "name")) == Some("og:image")
+        })
+        .and_then(|tag| attribute(tag, "content"))
+        .and_then(|url| base.join(&url.replace("&amp;", "&")).ok())
+}
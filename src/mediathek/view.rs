@@ -1,26 +1,22 @@
 // SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{
-    cell::{Cell, OnceCell, RefCell},
-    sync::Arc,
-};
+use std::cell::{Cell, OnceCell, RefCell};
 
 use adw::{gio, glib, gtk, prelude::*, subclass::prelude::*};
 use eyre::WrapErr;
 use gettextrs::gettext;
-use mediathekviewweb::{
-    models::{SortField, SortOrder},
-    Mediathek,
-};
+use mediathekviewweb::models::{SortField, SortOrder};
 
 use crate::{
-    config::{APP_ID, PROJECT_URL, VERSION},
+    application::TvApplication,
+    error::{self, retry, ApiError},
+    network,
     settings::TvSettings,
-    utils::{show_error, spawn, spawn_clone, tokio},
+    utils::{spawn, spawn_clone, tokio},
 };
 
-use super::{card::TvMediathekCard, shows::ShowObject};
+use super::{card::TvMediathekCard, filter::ContentFilter, shows::ShowObject};
 
 mod imp {
     use super::*;
@@ -42,6 +38,10 @@ mod imp {
         #[template_child]
         nothing_found_view: TemplateChild<adw::StatusPage>,
         #[template_child]
+        error_view: TemplateChild<adw::StatusPage>,
+        #[template_child]
+        offline_view: TemplateChild<adw::StatusPage>,
+        #[template_child]
         results_view: TemplateChild<gtk::ScrolledWindow>,
 
         #[property(get, set)]
@@ -63,8 +63,9 @@ mod imp {
         total_results: Cell<u64>,
         #[property(get, set)]
         more_available: Cell<bool>,
+        /// The number of results that were loaded, including the ones removed by the content filter.
+        pub(super) loaded_results: Cell<u64>,
 
-        pub(super) client: OnceCell<Arc<Mediathek>>,
         pub(super) shows_model: OnceCell<gio::ListStore>,
     }
     impl TvMediathekView {
@@ -76,6 +77,43 @@ mod imp {
         pub(super) fn show_status_page(&self) {
             self.stack.set_visible_child(&*self.status_page)
         }
+        pub(super) fn show_offline_page(&self) {
+            self.stack.set_visible_child(&*self.offline_view)
+        }
+        pub(super) fn show_error_page(&self, e: &eyre::Report) {
+            error::show_on_status_page(e, &self.error_view);
+            self.stack.set_visible_child(&*self.error_view)
+        }
+        /// Shows the results or, if all of them were filtered out, that nothing was found.
+        pub(super) fn show_results_page(&self) {
+            if self.shows_model().n_items() == 0 && !self.more_available.get() {
+                self.stack.set_visible_child(&*self.nothing_found_view);
+            } else {
+                self.stack.set_visible_child(&*self.results_view);
+            }
+        }
+        /// Loads the details of the cards that are currently in the viewport.
+        fn load_visible_cards(&self) {
+            let viewport_height = self.results_view.height() as f32;
+
+            let mut index = 0;
+            while let Some(row) = self.results_list.row_at_index(index) {
+                index += 1;
+
+                let Some(bounds) = row.compute_bounds(&*self.results_view) else {
+                    continue;
+                };
+                if bounds.y() > viewport_height {
+                    break;
+                }
+                if bounds.y() + bounds.height() < 0. {
+                    continue;
+                }
+                if let Some(card) = row.downcast_ref::<TvMediathekCard>() {
+                    card.load_details();
+                }
+            }
+        }
     }
 
     #[glib::object_subclass]
@@ -144,6 +182,25 @@ mod imp {
                         .into()
                 });
 
+            // details of the results are only loaded once they are scrolled into view
+            let adjustment = self.results_view.vadjustment();
+            adjustment.connect_value_changed(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |_| slf.load_visible_cards()
+            ));
+            adjustment.connect_page_size_notify(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |_| slf.load_visible_cards()
+            ));
+            // new results or hidden cards change the size of the list
+            adjustment.connect_upper_notify(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |_| slf.load_visible_cards()
+            ));
+
             self.results_list.connect_row_activated(|_, row| {
                 let row = row
                     .downcast_ref::<TvMediathekCard>()
@@ -165,17 +222,59 @@ mod imp {
             slf.connect_sort_by_notify(load);
             slf.connect_sort_order_notify(load);
 
+            settings.connect_min_duration_changed(glib::clone!(
+                #[weak]
+                slf,
+                move |_| load(&slf)
+            ));
+            settings.connect_audio_description_filter_changed(glib::clone!(
+                #[weak]
+                slf,
+                move |_| load(&slf)
+            ));
+            settings.connect_sign_language_filter_changed(glib::clone!(
+                #[weak]
+                slf,
+                move |_| load(&slf)
+            ));
+            settings.connect_blocked_topics_changed(glib::clone!(
+                #[weak]
+                slf,
+                move |_| load(&slf)
+            ));
+            settings.connect_blocked_channels_changed(glib::clone!(
+                #[weak]
+                slf,
+                move |_| load(&slf)
+            ));
+
             self.shows_model().connect_items_changed(glib::clone!(
                 #[weak(rename_to = slf)]
                 self,
-                move |results, _, _, _| {
-                    if results.n_items() == 0 {
-                        slf.stack.set_visible_child(&*slf.nothing_found_view);
-                    } else {
-                        slf.stack.set_visible_child(&*slf.results_view);
+                move |_, _, _, _| {
+                    slf.show_results_page();
+
+                    // the new rows are only positioned after the next layout
+                    glib::idle_add_local_once(glib::clone!(
+                        #[weak]
+                        slf,
+                        move || slf.load_visible_cards()
+                    ));
+                }
+            ));
+
+            network::connect_online_changed(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |online| {
+                    let visible_child = slf.stack.visible_child();
+                    let failed = visible_child.as_ref()
+                        == Some(slf.offline_view.upcast_ref::<gtk::Widget>())
+                        || visible_child.as_ref()
+                            == Some(slf.error_view.upcast_ref::<gtk::Widget>());
+                    if online && failed {
+                        slf.obj().reload();
                     }
-                    slf.obj()
-                        .set_more_available((results.n_items() as u64) < slf.total_results.get());
                 }
             ));
 
@@ -194,127 +293,146 @@ glib::wrapper! {
 }
 
 impl TvMediathekView {
-    fn client(&self) -> Arc<Mediathek> {
-        self.imp()
-            .client
-            .get_or_init(|| {
-                Arc::new(
-                    Mediathek::new(
-                        format!("{APP_ID}/{VERSION} ({PROJECT_URL})")
-                            .try_into()
-                            .expect("invalid user agent"),
-                    )
-                    .expect("failed to initialize HTTP client"),
-                )
-            })
-            .clone()
-    }
     fn query_props(&self) -> QueryProperties {
-        let query_string = self.query_string();
-
-        let search_everywhere = self.search_everywhere();
-        let include_future = self.include_future();
-        let sort_by = match &*self.sort_by() {
-            "channel" => SortField::Channel,
-            "date" | "timestamp" => SortField::Timestamp,
-            "duration" => SortField::Duration,
-            _ => SortField::Timestamp,
-        };
-        let sort_order = match &*self.sort_order() {
-            "asc" | "ascending" => SortOrder::Ascending,
-            "desc" | "descending" => SortOrder::Descending,
-            _ => SortOrder::Descending,
-        };
-
         QueryProperties {
-            query_string,
-            search_everywhere,
-            include_future,
-            sort_by,
-            sort_order,
+            query_string: self.query_string(),
+            search_everywhere: self.search_everywhere(),
+            include_future: self.include_future(),
+            sort_by: self.sort_by(),
+            sort_order: self.sort_order(),
         }
     }
     async fn load(&self) {
-        let QueryProperties {
-            query_string,
-            search_everywhere,
-            include_future,
-            sort_by,
-            sort_order,
-        } = self.query_props();
-
-        if query_string.is_empty() {
+        let props = self.query_props();
+
+        if props.query_string.is_empty() {
             self.imp().show_status_page();
             return;
         }
 
-        let client = self.client();
-
         let mut shows_model = self.imp().shows_model();
 
+        // results that have already been loaded are kept while offline
+        if !network::is_online() {
+            if shows_model.n_items() == 0 {
+                self.imp().show_offline_page();
+            }
+            return;
+        }
+
+        let client = TvApplication::get().mediathek();
+
         match tokio(async move {
-            client
-                .query_string(&query_string, search_everywhere)
-                .include_future(include_future)
-                .size(15)
-                .sort_by(sort_by)
-                .sort_order(sort_order)
-                .send()
-                .await
-                .wrap_err_with(|| gettext("Failed to query the MediathekViewWeb API"))
+            let (client, props) = (&client, &props);
+
+            retry(move || async move {
+                client
+                    .query_string(&props.query_string, props.search_everywhere)
+                    .include_future(props.include_future)
+                    .size(15)
+                    .sort_by(props.sort_field())
+                    .sort_order(props.sort_order())
+                    .send()
+                    .await
+                    .map_err(ApiError::new)
+            })
+            .await
+            .wrap_err_with(|| gettext("Failed to query the MediathekViewWeb API"))
         })
         .await
         {
             Ok(result) => {
-                shows_model.remove_all();
-                shows_model.extend(result.results.into_iter().map(ShowObject::new));
+                self.update_loaded_results(
+                    0,
+                    result.results.len(),
+                    result.query_info.total_results,
+                );
 
-                self.set_total_results(result.query_info.total_results);
+                shows_model.remove_all();
+                shows_model.extend(
+                    ContentFilter::from_settings()
+                        .apply(result.results)
+                        .into_iter()
+                        .map(ShowObject::new),
+                );
+                // the model doesn't emit `items-changed` if it was and stays empty
+                self.imp().show_results_page();
             }
-            Err(e) => show_error(e),
+            Err(e) => self.imp().show_error_page(&e),
         }
     }
     async fn load_more(&self) {
-        let QueryProperties {
-            query_string,
-            search_everywhere,
-            include_future,
-            sort_by,
-            sort_order,
-        } = self.query_props();
-
-        if query_string.is_empty() {
+        let props = self.query_props();
+
+        if props.query_string.is_empty() {
             self.imp().show_status_page();
             return;
         }
 
         let mut shows_model = self.imp().shows_model();
-        let offset = shows_model.n_items();
+        let offset = self.imp().loaded_results.get();
 
-        let client = self.client();
+        let client = TvApplication::get().mediathek();
 
         match tokio(async move {
-            client
-                .query_string(&query_string, search_everywhere)
-                .include_future(include_future)
-                .size(15)
-                .offset(offset as usize)
-                .sort_by(sort_by)
-                .sort_order(sort_order)
-                .send()
-                .await
-                .wrap_err_with(|| gettext("Failed to query the MediathekViewWeb API"))
+            let (client, props) = (&client, &props);
+
+            retry(move || async move {
+                client
+                    .query_string(&props.query_string, props.search_everywhere)
+                    .include_future(props.include_future)
+                    .size(15)
+                    .offset(offset as usize)
+                    .sort_by(props.sort_field())
+                    .sort_order(props.sort_order())
+                    .send()
+                    .await
+                    .map_err(ApiError::new)
+            })
+            .await
+            .wrap_err_with(|| gettext("Failed to query the MediathekViewWeb API"))
         })
         .await
         {
             Ok(result) => {
-                shows_model.extend(result.results.into_iter().map(ShowObject::new));
-
-                self.set_total_results(result.query_info.total_results);
+                self.update_loaded_results(
+                    offset,
+                    result.results.len(),
+                    result.query_info.total_results,
+                );
+
+                shows_model.extend(
+                    ContentFilter::from_settings()
+                        .apply(result.results)
+                        .into_iter()
+                        .map(ShowObject::new),
+                );
+                self.imp().show_results_page();
+            }
+            Err(e) => {
+                tracing::error!("{e:?}");
+
+                let title = match ApiError::find(&e) {
+                    Some(api_error) => api_error.title(),
+                    None => e.to_string(),
+                };
+                TvApplication::get().window().add_toast(
+                    adw::Toast::builder()
+                        .title(title)
+                        .button_label(gettext("Retry"))
+                        .action_name("mediathek.load-more")
+                        .build(),
+                );
             }
-            Err(e) => show_error(e),
         }
     }
+    fn update_loaded_results(&self, offset: u64, n_results: usize, total_results: u64) {
+        let loaded_results = offset + n_results as u64;
+
+        self.imp().loaded_results.set(loaded_results);
+        self.set_total_results(total_results);
+        self.set_more_available(loaded_results < total_results);
+    }
     pub fn reload(&self) {
         let slf = self.clone();
         spawn(async move { slf.load().await });
@@ -326,6 +444,24 @@ struct QueryProperties {
     query_string: String,
     search_everywhere: bool,
     include_future: bool,
-    sort_by: SortField,
-    sort_order: SortOrder,
+    sort_by: String,
+    sort_order: String,
+}
+
+impl QueryProperties {
+    fn sort_field(&self) -> SortField {
+        match &*self.sort_by {
+            "channel" => SortField::Channel,
+            "date" | "timestamp" => SortField::Timestamp,
+            "duration" => SortField::Duration,
+            _ => SortField::Timestamp,
+        }
+    }
+    fn sort_order(&self) -> SortOrder {
+        match &*self.sort_order {
+            "asc" | "ascending" => SortOrder::Ascending,
+            "desc" | "descending" => SortOrder::Descending,
+            _ => SortOrder::Descending,
+        }
+    }
 }
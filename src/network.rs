@@ -0,0 +1,64 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Network state based on [`gio::NetworkMonitor`].
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use adw::{gio, prelude::*};
+
+use crate::settings::TvSettings;
+
+/// Whether data usage should be reduced, e.g. by playing videos in low quality.
+///
+/// Depending on the `bandwidth-saver` setting, this is enabled automatically on metered connections.
+pub fn bandwidth_saver() -> bool {
+    match TvSettings::get().bandwidth_saver().as_str() {
+        "always" => true,
+        "never" => false,
+        _ => gio::NetworkMonitor::default().is_network_metered(),
+    }
+}
+
+/// Calls `f` whenever the network or the setting changes, which might change [`bandwidth_saver`].
+pub fn connect_bandwidth_saver_changed(f: impl Fn(bool) + 'static) {
+    let f = Rc::new(f);
+
+    gio::NetworkMonitor::default().connect_network_metered_notify({
+        let f = f.clone();
+        move |_| f(bandwidth_saver())
+    });
+    TvSettings::get().connect_bandwidth_saver_changed(move |_| f(bandwidth_saver()));
+}
+
+/// The availability of the network as last seen on the main thread.
+static ONLINE: AtomicBool = AtomicBool::new(true);
+
+/// Whether the network is available, which might still not include internet access.
+pub fn is_online() -> bool {
+    let online = gio::NetworkMonitor::default().is_network_available();
+    ONLINE.store(online, Ordering::Relaxed);
+    online
+}
+
+/// Like [`is_online`], but can be called from other threads, e.g. within the tokio runtime.
+pub fn was_online() -> bool {
+    ONLINE.load(Ordering::Relaxed)
+}
+
+/// Calls `f` when the network becomes available or unavailable.
+pub fn connect_online_changed(f: impl Fn(bool) + 'static) {
+    // `network-changed` is also emitted for changes that don't affect the availability
+    let online = Cell::new(is_online());
+
+    gio::NetworkMonitor::default().connect_network_changed(move |_, available| {
+        ONLINE.store(available, Ordering::Relaxed);
+        if online.replace(available) != available {
+            f(available)
+        }
+    });
+}
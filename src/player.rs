@@ -1,20 +1,40 @@
 // SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::cell::{Cell, RefCell};
+use std::{
+    cell::{Cell, OnceCell, RefCell},
+    collections::BTreeSet,
+    time::Duration,
+};
 
 use adw::{gio, glib, gtk, prelude::*, subclass::prelude::*};
 
 use eyre::WrapErr;
+use gettextrs::gettext;
+use gst::prelude::*;
+use time::OffsetDateTime;
 use tracing::error;
 
 use crate::{
     application::TvApplication,
-    channel_icons::channel_icon_resource,
+    channel_icons::export_channel_icon,
     config::{APP_ID, APP_NAME},
+    hls::{self, Variant, VariantPreference},
+    mediathek::{Availability, VideoFileInfo},
+    network, remote_control,
     settings::{TvPlayerSettings, VideoQuality},
-    utils::{spawn, tokio},
+    utils::{format_duration, format_timestamp_time, show_error, spawn},
+    zapp::{ChannelId, ChannelInfo, Show},
 };
+
+/// Seekable ranges shorter than this are not considered a DVR window.
+const MIN_DVR_WINDOW: f64 = 60.0;
+
+/// The volume is faded out during the last seconds of the sleep timer.
+const SLEEP_TIMER_FADE_OUT: u64 = 30;
+/// Volume changes smaller than this are attributed to rounding rather than to the user.
+const VOLUME_TOLERANCE: f64 = 0.01;
+
 mod imp {
     use super::*;
 
@@ -28,6 +48,10 @@ mod imp {
         pub(super) clapper_menu_button: TemplateChild<clapper_gtk::ExtraMenuButton>,
         #[template_child]
         pub(super) custom_menu_button: TemplateChild<gtk::MenuButton>,
+        #[template_child]
+        pub(super) channel_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub(super) live_quality_button: TemplateChild<gtk::MenuButton>,
 
         #[property(
             name = "player",
@@ -46,6 +70,29 @@ mod imp {
 
         #[property(get, set)]
         subtitles_enabled: Cell<bool>,
+        #[property(get)]
+        pub(super) dvr_available: Cell<bool>,
+        pub(super) seekable_range: Cell<Option<(f64, f64)>>,
+        pub(super) taginject: OnceCell<gst::Element>,
+
+        /// Live channels that can be switched to while a live channel is played.
+        pub(super) zap_channels: RefCell<Vec<(ChannelId, ChannelInfo)>>,
+        #[property(get)]
+        pub(super) zapping_available: Cell<bool>,
+        #[property(get, set)]
+        channel_list_visible: Cell<bool>,
+        /// Set while the queue is rebuilt to ignore the resulting changes of the current item.
+        pub(super) updating_queue: Cell<bool>,
+
+        /// The master playlist of the current live channel.
+        pub(super) live_master_uri: RefCell<Option<String>>,
+        pub(super) live_variants: RefCell<Vec<Variant>>,
+        #[property(get)]
+        pub(super) live_variants_available: Cell<bool>,
+        /// The quality that was played before the bandwidth saver switched to low quality.
+        pub(super) quality_before_bandwidth_saver: Cell<Option<VideoQuality>>,
+        #[property(get, set)]
+        live_variant: RefCell<String>,
 
         #[property(get, set)]
         title: RefCell<String>,
@@ -59,6 +106,44 @@ mod imp {
         uri_medium: RefCell<Option<String>>,
         #[property(get, set, nullable)]
         uri_low: RefCell<Option<String>>,
+        #[property(get, set, nullable)]
+        live_channel_id: RefCell<Option<String>>,
+
+        #[property(get, set, nullable)]
+        show_title: RefCell<Option<String>>,
+        #[property(get, set, nullable)]
+        show_subtitle: RefCell<Option<String>>,
+        #[property(get, set)]
+        show_timespan: RefCell<String>,
+        #[property(get)]
+        pub(super) has_show: Cell<bool>,
+        #[property(get)]
+        pub(super) has_show_subtitle: Cell<bool>,
+        #[property(get, set)]
+        header_title: RefCell<String>,
+        #[property(get, set)]
+        header_subtitle: RefCell<String>,
+        #[property(get, set)]
+        osd_visible: Cell<bool>,
+        /// Incremented whenever the OSD is shown, so only the latest timeout hides it.
+        pub(super) osd_generation: Cell<u64>,
+        /// Incremented whenever the channel changes, so outdated EPG updates are discarded.
+        pub(super) epg_generation: Cell<u64>,
+
+        #[property(get)]
+        pub(super) sleep_timer_active: Cell<bool>,
+        /// Set if the current Mediathek video failed to play because it is geo-blocked.
+        #[property(get)]
+        pub(super) geo_blocked: Cell<bool>,
+        #[property(get, set)]
+        sleep_timer_remaining: RefCell<String>,
+        #[property(get, set)]
+        sleep_timer_action: RefCell<String>,
+        #[property(get, set)]
+        sleep_timer_quit: Cell<bool>,
+        /// Incremented whenever a sleep timer is started or cancelled.
+        pub(super) sleep_timer_generation: Cell<u64>,
+        pub(super) sleep_timer_fade: Cell<SleepTimerFade>,
     }
 
     #[glib::object_subclass]
@@ -84,6 +169,76 @@ mod imp {
             });
 
             klass.install_property_action("player.enable-subtitles", "subtitles-enabled");
+
+            klass.install_action_async("player.restart-show", None, |slf, _, _| async move {
+                slf.restart_show().await
+            });
+            klass.install_action("player.seek-backward", None, |slf, _, _| {
+                slf.seek_relative(-30.0)
+            });
+            klass.install_action("player.seek-forward", None, |slf, _, _| {
+                slf.seek_relative(30.0)
+            });
+            klass.install_action("player.seek-to-live", None, |slf, _, _| slf.seek_to_live());
+
+            klass.install_action("player.next-channel", None, |slf, _, _| slf.zap_relative(1));
+            klass.install_action("player.previous-channel", None, |slf, _, _| {
+                slf.zap_relative(-1)
+            });
+            klass.install_property_action("player.toggle-channel-list", "channel-list-visible");
+            klass.install_property_action("player.live-variant", "live-variant");
+
+            klass.install_action(
+                "player.sleep-timer",
+                Some(glib::VariantTy::STRING),
+                |slf, _, variant| {
+                    if let Some(minutes) = variant
+                        .and_then(|v| v.get::<String>())
+                        .and_then(|minutes| minutes.parse::<u64>().ok())
+                    {
+                        slf.start_sleep_timer(minutes * 60)
+                    }
+                },
+            );
+            klass.install_action_async(
+                "player.sleep-timer-end-of-show",
+                None,
+                |slf, _, _| async move { slf.start_sleep_timer_end_of_show().await },
+            );
+            klass.install_action("player.cancel-sleep-timer", None, |slf, _, _| {
+                slf.cancel_sleep_timer()
+            });
+            klass.install_property_action("player.sleep-timer-action", "sleep-timer-action");
+            klass.install_property_action("player.sleep-timer-quit", "sleep-timer-quit");
+
+            klass.add_binding_action(
+                gtk::gdk::Key::Page_Up,
+                gtk::gdk::ModifierType::empty(),
+                "player.next-channel",
+            );
+            klass.add_binding_action(
+                gtk::gdk::Key::Page_Down,
+                gtk::gdk::ModifierType::empty(),
+                "player.previous-channel",
+            );
+            for (number, keys) in [
+                (1, [gtk::gdk::Key::_1, gtk::gdk::Key::KP_1]),
+                (2, [gtk::gdk::Key::_2, gtk::gdk::Key::KP_2]),
+                (3, [gtk::gdk::Key::_3, gtk::gdk::Key::KP_3]),
+                (4, [gtk::gdk::Key::_4, gtk::gdk::Key::KP_4]),
+                (5, [gtk::gdk::Key::_5, gtk::gdk::Key::KP_5]),
+                (6, [gtk::gdk::Key::_6, gtk::gdk::Key::KP_6]),
+                (7, [gtk::gdk::Key::_7, gtk::gdk::Key::KP_7]),
+                (8, [gtk::gdk::Key::_8, gtk::gdk::Key::KP_8]),
+                (9, [gtk::gdk::Key::_9, gtk::gdk::Key::KP_9]),
+            ] {
+                for key in keys {
+                    klass.add_binding(key, gtk::gdk::ModifierType::empty(), move |slf| {
+                        slf.zap_to(number - 1);
+                        glib::Propagation::Stop
+                    });
+                }
+            }
         }
 
         fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
@@ -106,20 +261,54 @@ mod imp {
                 .expect("failed to create `taginject` element");
 
             slf.player().set_video_filter(Some(&taginject));
+            self.taginject
+                .set(taginject)
+                .expect("taginject has already been initialized");
+
+            slf.connect_live_variant_notify(|slf| slf.apply_live_variant());
+            network::connect_bandwidth_saver_changed(glib::clone!(
+                #[weak]
+                slf,
+                move |active| slf.on_bandwidth_saver_changed(active)
+            ));
+
+            // playback errors caused by geo-blocking are explained instead of GStreamer's generic error
+            slf.player().connect_local(
+                "error",
+                false,
+                glib::clone!(
+                    #[weak]
+                    slf,
+                    #[upgrade_or]
+                    None,
+                    move |_| {
+                        spawn(async move { slf.check_geo_blocked().await });
+                        None
+                    }
+                ),
+            );
+            slf.connect_uri_notify(|slf| slf.set_geo_blocked(false));
+
+            slf.connect_title_notify(|slf| slf.update_title());
+            slf.connect_show_title_notify(|slf| slf.update_title());
+            slf.connect_show_subtitle_notify(|slf| slf.update_title());
+
+            // switching items in the queue (e.g. via MPRIS) switches the channel
+            slf.player()
+                .queue()
+                .expect("should not be nullable")
+                .connect_current_index_notify(glib::clone!(
+                    #[weak]
+                    slf,
+                    move |queue| slf.on_current_index_changed(queue.current_index())
+                ));
 
-            slf.connect_title_notify(glib::clone!(
+            self.channel_list.connect_row_activated(glib::clone!(
                 #[weak]
-                taginject,
-                move |slf| {
-                    let mut tags = gst::TagList::new();
-                    tags.make_mut()
-                        .add::<gst::tags::Title>(&&*slf.title(), gst::TagMergeMode::Replace);
-                    taginject.set_property(
-                        "tags",
-                        tags.to_string()
-                            .strip_prefix("taglist, ")
-                            .expect("serialized GstTagList should start with `taglist, `"),
-                    )
+                slf,
+                move |_, row| {
+                    slf.zap_to(row.index() as usize);
+                    slf.set_channel_list_visible(false);
                 }
             ));
 
@@ -142,6 +331,30 @@ mod imp {
             settings
                 .bind_subtitles_enabled(&*slf, "subtitles-enabled")
                 .build();
+            settings
+                .bind_sleep_timer_action(&*slf, "sleep-timer-action")
+                .build();
+            settings
+                .bind_sleep_timer_quit(&*slf, "sleep-timer-quit")
+                .build();
+
+            slf.action_set_enabled("player.cancel-sleep-timer", false);
+            slf.action_set_enabled("player.sleep-timer-end-of-show", false);
+            slf.connect_has_show_notify(|slf| {
+                slf.action_set_enabled("player.sleep-timer-end-of-show", slf.has_show())
+            });
+
+            // the DVR window of a live stream moves over time, so it is checked regularly
+            slf.update_seekable_range();
+            let slf = slf.downgrade();
+            spawn(async move {
+                loop {
+                    glib::timeout_future_seconds(5).await;
+
+                    let Some(slf) = slf.upgrade() else { break };
+                    slf.update_seekable_range();
+                }
+            });
         }
     }
     impl WidgetImpl for TvPlayer {}
@@ -160,6 +373,27 @@ impl TvPlayer {
             .property("application", application)
             .build()
     }
+    /// Sets the live channels that can be switched to, in the user's order.
+    pub fn set_live_channels(&self, channels: Vec<(ChannelId, ChannelInfo)>) {
+        let imp = self.imp();
+
+        imp.channel_list.remove_all();
+        for (number, (_, channel_info)) in channels.iter().enumerate() {
+            let row = adw::ActionRow::builder()
+                .title(glib::markup_escape_text(&channel_info.name))
+                .activatable(true)
+                .build();
+            row.add_prefix(
+                &gtk::Label::builder()
+                    .label((number + 1).to_string())
+                    .css_classes(["numeric", "dim-label"])
+                    .build(),
+            );
+            imp.channel_list.append(&row);
+        }
+
+        imp.zap_channels.replace(channels);
+    }
     pub fn play(&self, video: VideoInfo) {
         self.player().stop();
 
@@ -170,13 +404,16 @@ impl TvPlayer {
                 channel_id,
             } => {
                 self.set_title(title);
+                self.set_live_channel_id(Some(channel_id.as_str()));
                 self.set_channel_icon(channel_id);
 
                 self.imp().seek_bar.set_reveal_labels(false);
                 self.imp().clapper_menu_button.set_visible(true);
                 self.imp().custom_menu_button.set_visible(false);
 
-                self.set_uri(uri);
+                self.set_uri(uri.as_str());
+                self.imp().live_master_uri.replace(Some(uri));
+                self.update_current_show();
                 self.set_subtitle_uri(None::<&str>);
                 self.set_uri_high(None::<&str>);
                 self.set_uri_medium(None::<&str>);
@@ -192,7 +429,9 @@ impl TvPlayer {
                 channel_id,
             } => {
                 self.set_title(title);
+                self.set_live_channel_id(None::<&str>);
                 self.set_channel_icon(channel_id);
+                self.update_current_show();
 
                 self.imp().seek_bar.set_reveal_labels(true);
                 self.imp().clapper_menu_button.set_visible(false);
@@ -207,46 +446,573 @@ impl TvPlayer {
                 self.set_uri_medium(uri_medium);
                 self.set_uri_low(uri_low);
                 self.set_quality(preferred_quality);
+                self.imp().live_master_uri.replace(None);
             }
         }
+        self.update_zapping_available();
         self.stop_playback();
+        self.update_seekable_range();
+
+        // playback of live streams is started once the variant is known if it isn't the master playlist
+        let deferred = self
+            .live_channel_id()
+            .is_some_and(|channel_id| VariantPreference::for_channel(&channel_id).needs_variants());
+        if !deferred {
+            self.start_playback();
+        }
+        self.load_live_variants(deferred);
+    }
+    /// Loads the variants of the current live stream and switches to the preferred one.
+    fn load_live_variants(&self, start_playback: bool) {
+        let imp = self.imp();
+        imp.live_variants.replace(Vec::new());
+        self.update_live_variant_menu();
+
+        let (Some(channel_id), Some(master_uri)) =
+            (self.live_channel_id(), imp.live_master_uri.borrow().clone())
+        else {
+            return;
+        };
+        let slf = self.downgrade();
+
+        spawn(async move {
+            let variants = match hls::fetch_variants(master_uri).await {
+                Ok(variants) => variants,
+                Err(e) => {
+                    error!("failed to load HLS variants: {e:?}");
+                    Vec::new()
+                }
+            };
+
+            let Some(slf) = slf.upgrade() else { return };
+            if slf.live_channel_id().as_ref() != Some(&channel_id) {
+                return;
+            }
+
+            let preference = VariantPreference::for_channel(&channel_id);
+            slf.imp().live_variants.replace(variants);
+            slf.update_live_variant_menu();
+
+            if let Some(uri) = slf.live_variant_uri(preference) {
+                if start_playback || uri != slf.uri() {
+                    slf.set_uri(uri);
+                    slf.start_playback();
+                }
+            }
+            slf.set_live_variant(preference.to_string());
+        });
+    }
+    /// Switches to the variant selected by the user and remembers it for the channel.
+    fn apply_live_variant(&self) {
+        let Some(channel_id) = self.live_channel_id() else {
+            return;
+        };
+        let preference = self
+            .live_variant()
+            .parse::<VariantPreference>()
+            .unwrap_or_default();
+        preference.save(&channel_id);
+
+        if let Some(uri) = self.live_variant_uri(preference) {
+            if uri != self.uri() {
+                self.set_uri(uri);
+                self.start_playback();
+            }
+        }
+    }
+    fn live_variant_uri(&self, preference: VariantPreference) -> Option<String> {
+        let imp = self.imp();
+        let master_uri = imp.live_master_uri.borrow().clone()?;
+
+        Some(
+            preference
+                .select(&imp.live_variants.borrow())
+                .map(|variant| variant.uri.clone())
+                .unwrap_or(master_uri),
+        )
+    }
+    fn update_live_variant_menu(&self) {
+        let imp = self.imp();
+        let variants = imp.live_variants.borrow();
+
+        let menu = gio::Menu::new();
+        let item = |preference: VariantPreference, label: &str| {
+            let item = gio::MenuItem::new(Some(label), None);
+            item.set_action_and_target_value(
+                Some("player.live-variant"),
+                Some(&preference.to_string().to_variant()),
+            );
+            item
+        };
+
+        let section = gio::Menu::new();
+        for preference in [VariantPreference::Default, VariantPreference::Auto] {
+            section.append_item(&item(preference, &preference.label()));
+        }
+        menu.append_section(None, &section);
+
+        let section = gio::Menu::new();
+        for variant in variants.iter() {
+            section.append_item(&item(
+                VariantPreference::Pin(variant.bandwidth),
+                &variant.label(),
+            ));
+        }
+        menu.append_section(Some(&gettext("Variant")), &section);
+
+        // the variants are sorted by bandwidth, which doesn't imply that their heights are sorted
+        let heights = variants
+            .iter()
+            .filter_map(Variant::height)
+            .collect::<BTreeSet<_>>();
+        let section = gio::Menu::new();
+        for height in heights.into_iter().rev() {
+            let preference = VariantPreference::MaxHeight(height);
+            section.append_item(&item(preference, &preference.label()));
+        }
+        menu.append_section(Some(&gettext("Limit Resolution")), &section);
+
+        imp.live_quality_button.set_menu_model(Some(&menu));
+
+        let available = !variants.is_empty();
+        if imp.live_variants_available.replace(available) != available {
+            self.notify_live_variants_available();
+        }
+    }
+    /// Switches the quality of the current video when the bandwidth saver is (de)activated.
+    fn on_bandwidth_saver_changed(&self, active: bool) {
+        let imp = self.imp();
+
+        if self.live_channel_id().is_some() {
+            self.load_live_variants(false);
+            // the programme is not loaded while the bandwidth saver is active
+            if !active {
+                self.update_current_show();
+            }
+            return;
+        }
+        if self.uri().is_empty() {
+            return;
+        }
+
+        let quality = if active {
+            if self.uri_low().is_none() || self.uri_low().as_deref() == Some(&*self.uri()) {
+                return;
+            }
+            let current = [VideoQuality::High, VideoQuality::Medium]
+                .into_iter()
+                .find(|quality| self.video_uri(*quality).as_deref() == Some(&*self.uri()));
+            imp.quality_before_bandwidth_saver.set(current);
+            VideoQuality::Low
+        } else {
+            let Some(quality) = imp.quality_before_bandwidth_saver.take() else {
+                return;
+            };
+            quality
+        };
+
+        self.set_quality(quality);
         self.start_playback();
     }
-    fn set_channel_icon(&self, channel_id: String) {
-        let mpris = self.mpris();
-        if let Some(icon_resource) = channel_icon_resource(&channel_id) {
-            spawn(async move {
-                if let Err(e) = async {
-                    let icon_data =
-                        gio::resources_lookup_data(&icon_resource, gio::ResourceLookupFlags::NONE)
-                            .wrap_err("failed to load resource bytes")?;
-
-                    let icon_path =
-                        glib::user_cache_dir().join(format!("televido/{channel_id}.svg"));
-
-                    tokio({
-                        let icon_path = icon_path.clone();
-                        async move {
-                            tokio::fs::create_dir_all(icon_path.parent().unwrap()).await?;
-                            tokio::fs::write(&icon_path, icon_data).await?;
-                            Ok::<(), eyre::Report>(())
+    fn video_uri(&self, quality: VideoQuality) -> Option<String> {
+        match quality {
+            VideoQuality::High => self.uri_high(),
+            VideoQuality::Medium => self.uri_medium(),
+            VideoQuality::Low => self.uri_low(),
+        }
+    }
+    fn current_channel_index(&self) -> Option<usize> {
+        let channel_id = self.live_channel_id()?;
+        self.imp()
+            .zap_channels
+            .borrow()
+            .iter()
+            .position(|(id, _)| id.as_ref() == channel_id)
+    }
+    fn update_zapping_available(&self) {
+        let imp = self.imp();
+
+        let available =
+            self.current_channel_index().is_some() && imp.zap_channels.borrow().len() > 1;
+        if imp.zapping_available.replace(available) != available {
+            self.notify_zapping_available();
+        }
+        if !available {
+            self.set_channel_list_visible(false);
+        }
+        for action in [
+            "player.next-channel",
+            "player.previous-channel",
+            "player.toggle-channel-list",
+        ] {
+            self.action_set_enabled(action, available);
+        }
+
+        match self.current_channel_index() {
+            Some(index) => imp
+                .channel_list
+                .select_row(imp.channel_list.row_at_index(index as i32).as_ref()),
+            None => imp.channel_list.unselect_all(),
+        }
+    }
+    fn zap_relative(&self, offset: isize) {
+        let Some(index) = self.current_channel_index() else {
+            return;
+        };
+        let n_channels = self.imp().zap_channels.borrow().len() as isize;
+
+        self.zap_to((index as isize + offset).rem_euclid(n_channels) as usize);
+    }
+    fn zap_to(&self, index: usize) {
+        if !self.zapping_available() || index >= self.imp().zap_channels.borrow().len() {
+            return;
+        }
+
+        let player = self.player();
+        player
+            .queue()
+            .expect("should not be nullable")
+            .select_index(index as u32);
+        player.play();
+    }
+    /// Updates the player after another live channel has been selected in the queue.
+    fn on_current_index_changed(&self, index: u32) {
+        if self.imp().updating_queue.get()
+            || self.live_channel_id().is_none()
+            || self.current_channel_index() == Some(index as usize)
+        {
+            return;
+        }
+        let Some((channel_id, channel_info)) = self
+            .imp()
+            .zap_channels
+            .borrow()
+            .get(index as usize)
+            .cloned()
+        else {
+            return;
+        };
+
+        let channel_id = channel_id.to_string();
+
+        remote_control::emit_playback_started(&VideoInfo::Live {
+            title: channel_info.name.clone(),
+            uri: channel_info.stream_url.clone(),
+            channel_id: channel_id.clone(),
+        });
+
+        self.set_title(channel_info.name);
+        self.set_live_channel_id(Some(channel_id.as_str()));
+        self.set_channel_icon(channel_id);
+        self.set_uri(channel_info.stream_url.as_str());
+        self.imp()
+            .live_master_uri
+            .replace(Some(channel_info.stream_url));
+        self.update_current_show();
+        self.load_live_variants(false);
+
+        self.update_zapping_available();
+        self.update_seekable_range();
+    }
+    /// Updates the header and the title tag, which is also used for the MPRIS metadata.
+    fn update_title(&self) {
+        let title = self.title();
+
+        let (header_title, header_subtitle) = match self.show_title() {
+            Some(show_title) => (
+                show_title,
+                [Some(title.clone()), self.show_subtitle()]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(" · "),
+            ),
+            None => (title.clone(), String::new()),
+        };
+        let tag_title = match self.show_title() {
+            Some(show_title) => format!("{show_title} – {title}"),
+            None => title,
+        };
+
+        self.set_header_title(header_title);
+        self.set_header_subtitle(header_subtitle);
+
+        if let Some(taginject) = self.imp().taginject.get() {
+            let mut tags = gst::TagList::new();
+            tags.make_mut()
+                .add::<gst::tags::Title>(&tag_title.as_str(), gst::TagMergeMode::Replace);
+            taginject.set_property(
+                "tags",
+                tags.to_string()
+                    .strip_prefix("taglist, ")
+                    .expect("serialized GstTagList should start with `taglist, `"),
+            )
+        }
+    }
+    /// Loads the current programme of a live channel and keeps it up to date.
+    fn update_current_show(&self) {
+        let imp = self.imp();
+        let generation = imp.epg_generation.get() + 1;
+        imp.epg_generation.set(generation);
+
+        self.set_current_show(None);
+
+        let Some(channel_id) = self.live_channel_id() else {
+            return;
+        };
+        let channel_id = ChannelId::from(channel_id);
+        let slf = self.downgrade();
+
+        spawn(async move {
+            let mut previous_title = None;
+
+            loop {
+                let application = TvApplication::get();
+                let show = if network::bandwidth_saver() {
+                    application.cached_current_show(&channel_id)
+                } else {
+                    match application.current_show(&channel_id).await {
+                        Ok(show) => show,
+                        Err(e) => {
+                            error!("{e:?}");
+                            None
                         }
-                    })
-                    .await?;
+                    }
+                };
 
-                    mpris.set_fallback_art_url(
-                        icon_path.to_str().map(|p| format!("file://{p}")).as_deref(),
-                    );
+                let Some(player) = slf.upgrade() else { break };
+                if player.imp().epg_generation.get() != generation {
+                    break;
+                }
+
+                let end_time = show.as_ref().map(|show| show.end_time);
+                let title = show.as_ref().map(|show| show.title.clone());
+                player.set_current_show(show);
 
-                    Ok::<(), eyre::Report>(())
+                // the OSD is shown when the channel starts and when the programme changes
+                if previous_title.as_ref() != Some(&title) {
+                    player.show_osd();
                 }
-                .await
-                {
-                    error!("{e:?}");
+                previous_title = Some(title);
+                drop(player);
+
+                // without EPG data, it is checked again from time to time
+                let delay = match end_time {
+                    Some(end_time) => {
+                        (end_time - OffsetDateTime::now_utc())
+                            .whole_seconds()
+                            .clamp(0, u32::MAX as i64 - 1) as u32
+                            + 1
+                    }
+                    None => 300,
+                };
+                glib::timeout_future_seconds(delay).await;
+            }
+        });
+    }
+    fn set_current_show(&self, show: Option<Show>) {
+        let imp = self.imp();
+        let has_show = show.is_some();
+        let has_show_subtitle = show
+            .as_ref()
+            .is_some_and(|show| show.subtitle.as_ref().is_some_and(|s| !s.is_empty()));
+
+        match show {
+            Some(show) => {
+                self.set_show_timespan(
+                    [show.start_time, show.end_time]
+                        .map(|t| format_timestamp_time(t.unix_timestamp()).unwrap_or_default())
+                        .join(" – "),
+                );
+                self.set_show_title(Some(show.title));
+                self.set_show_subtitle(show.subtitle.filter(|s| !s.is_empty()));
+            }
+            None => {
+                self.set_show_timespan("");
+                self.set_show_title(None::<&str>);
+                self.set_show_subtitle(None::<&str>);
+            }
+        }
+
+        if imp.has_show.replace(has_show) != has_show {
+            self.notify_has_show();
+        }
+        if imp.has_show_subtitle.replace(has_show_subtitle) != has_show_subtitle {
+            self.notify_has_show_subtitle();
+        }
+    }
+    /// Pauses or stops playback after the given number of seconds.
+    fn start_sleep_timer(&self, seconds: u64) {
+        let imp = self.imp();
+        let generation = imp.sleep_timer_generation.get() + 1;
+        imp.sleep_timer_generation.set(generation);
+        self.set_sleep_timer_active(true);
+
+        self.restore_sleep_timer_volume();
+
+        let deadline = glib::monotonic_time() + seconds as i64 * 1_000_000;
+        let slf = self.downgrade();
+
+        spawn(async move {
+            loop {
+                let Some(player) = slf.upgrade() else { break };
+                if player.imp().sleep_timer_generation.get() != generation {
+                    break;
                 }
-            });
+
+                let remaining = ((deadline - glib::monotonic_time()).max(0) / 1_000_000) as u64;
+                player.set_sleep_timer_remaining(format_duration(&Duration::from_secs(remaining)));
+
+                if remaining < SLEEP_TIMER_FADE_OUT {
+                    player.fade_out_sleep_timer(remaining as f64 / SLEEP_TIMER_FADE_OUT as f64);
+                }
+                if remaining == 0 {
+                    player.finish_sleep_timer();
+                    break;
+                }
+                drop(player);
+
+                glib::timeout_future_seconds(1).await;
+            }
+        });
+    }
+    async fn start_sleep_timer_end_of_show(&self) {
+        let Some(channel_id) = self.live_channel_id() else {
+            return;
+        };
+
+        match TvApplication::get()
+            .current_show(&ChannelId::from(channel_id))
+            .await
+        {
+            Ok(Some(show)) => self.start_sleep_timer(
+                (show.end_time - OffsetDateTime::now_utc())
+                    .whole_seconds()
+                    .max(0) as u64,
+            ),
+            Ok(None) => (),
+            Err(e) => show_error(e.wrap_err(gettext("Failed to load the current show"))),
         }
     }
+    fn cancel_sleep_timer(&self) {
+        let imp = self.imp();
+        imp.sleep_timer_generation
+            .set(imp.sleep_timer_generation.get() + 1);
+        self.restore_sleep_timer_volume();
+        self.set_sleep_timer_active(false);
+    }
+    fn finish_sleep_timer(&self) {
+        let player = self.player();
+
+        if self.sleep_timer_action() == "stop" {
+            player.stop();
+        } else {
+            player.pause();
+        }
+        self.restore_sleep_timer_volume();
+        self.set_sleep_timer_active(false);
+
+        if self.sleep_timer_quit() {
+            TvApplication::get().quit();
+        }
+    }
+    /// Lowers the volume to `factor` times the volume from before the fade-out.
+    ///
+    /// The fade-out stops if the user changes the volume in the meantime.
+    fn fade_out_sleep_timer(&self, factor: f64) {
+        let imp = self.imp();
+        let player = self.player();
+
+        let volume = match imp.sleep_timer_fade.get() {
+            SleepTimerFade::Inactive => player.volume(),
+            SleepTimerFade::Fading {
+                volume,
+                faded_volume,
+            } if (player.volume() - faded_volume).abs() < VOLUME_TOLERANCE => volume,
+            SleepTimerFade::Fading { .. } | SleepTimerFade::Overridden => {
+                imp.sleep_timer_fade.set(SleepTimerFade::Overridden);
+                return;
+            }
+        };
+
+        let faded_volume = volume * factor;
+        player.set_volume(faded_volume);
+        imp.sleep_timer_fade.set(SleepTimerFade::Fading {
+            volume,
+            faded_volume,
+        });
+    }
+    /// Resets the volume that was lowered by the fade-out, unless the user changed it.
+    fn restore_sleep_timer_volume(&self) {
+        let player = self.player();
+
+        if let SleepTimerFade::Fading {
+            volume,
+            faded_volume,
+        } = self
+            .imp()
+            .sleep_timer_fade
+            .replace(SleepTimerFade::Inactive)
+        {
+            if (player.volume() - faded_volume).abs() < VOLUME_TOLERANCE {
+                player.set_volume(volume);
+            }
+        }
+    }
+    fn set_sleep_timer_active(&self, active: bool) {
+        if self.imp().sleep_timer_active.replace(active) != active {
+            self.notify_sleep_timer_active();
+        }
+        self.action_set_enabled("player.cancel-sleep-timer", active);
+    }
+    fn set_geo_blocked(&self, geo_blocked: bool) {
+        if self.imp().geo_blocked.replace(geo_blocked) != geo_blocked {
+            self.notify_geo_blocked();
+        }
+    }
+    async fn check_geo_blocked(&self) {
+        if self.live_channel_id().is_some() {
+            return;
+        }
+
+        let uri = self.uri();
+        let availability = VideoFileInfo::probe(uri.clone()).await.availability;
+        // the video might have changed in the meantime
+        if self.uri() == uri {
+            self.set_geo_blocked(availability == Availability::GeoBlocked);
+        }
+    }
+    /// Briefly shows an overlay with the channel and the current programme.
+    fn show_osd(&self) {
+        let imp = self.imp();
+        let generation = imp.osd_generation.get() + 1;
+        imp.osd_generation.set(generation);
+
+        self.set_osd_visible(true);
+
+        let slf = self.downgrade();
+        spawn(async move {
+            glib::timeout_future_seconds(5).await;
+
+            if let Some(slf) = slf.upgrade() {
+                if slf.imp().osd_generation.get() == generation {
+                    slf.set_osd_visible(false);
+                }
+            }
+        });
+    }
+    fn set_channel_icon(&self, channel_id: String) {
+        let mpris = self.mpris();
+        spawn(async move {
+            match export_channel_icon(&channel_id, &glib::user_cache_dir().join("televido")).await {
+                Ok(Some(icon_path)) => mpris.set_fallback_art_url(
+                    icon_path.to_str().map(|p| format!("file://{p}")).as_deref(),
+                ),
+                Ok(None) => (),
+                Err(e) => error!("{e:?}"),
+            }
+        });
+    }
     fn set_quality(&self, quality: VideoQuality) {
         match quality {
             VideoQuality::High => {
@@ -266,16 +1032,37 @@ impl TvPlayer {
 
         let position = player.position();
 
-        let item = clapper::MediaItem::new(&self.uri());
+        self.imp().updating_queue.set(true);
+        queue.clear();
+
+        // all live channels are added to the queue, so they can be switched with MPRIS `Next`/`Previous`
+        match self.current_channel_index() {
+            Some(index) => {
+                for (i, (_, channel_info)) in self.imp().zap_channels.borrow().iter().enumerate() {
+                    // the current channel might use another variant than the master playlist
+                    let uri = if i == index {
+                        self.uri()
+                    } else {
+                        channel_info.stream_url.clone()
+                    };
+                    queue.add_item(&clapper::MediaItem::new(&uri));
+                }
+                queue.select_index(index as u32);
+            }
+            None => {
+                let item = clapper::MediaItem::new(&self.uri());
 
-        // Adding subtitles currently breaks playback
-        // see https://gitlab.freedesktop.org/gstreamer/gstreamer/-/issues/4066
-        // if let Some(subtitle_uri) = self.subtitle_uri() {
-        //     item.set_suburi(&subtitle_uri);
-        // }
+                // Adding subtitles currently breaks playback
+                // see https://gitlab.freedesktop.org/gstreamer/gstreamer/-/issues/4066
+                // if let Some(subtitle_uri) = self.subtitle_uri() {
+                //     item.set_suburi(&subtitle_uri);
+                // }
 
-        queue.add_item(&item);
-        queue.select_item(Some(&item));
+                queue.add_item(&item);
+                queue.select_item(Some(&item));
+            }
+        }
+        self.imp().updating_queue.set(false);
 
         // seek to previous position to continue playback
         // this should be a simple sequence of pause -> seek -> play
@@ -309,7 +1096,108 @@ impl TvPlayer {
     fn stop_playback(&self) {
         self.player().stop();
     }
+    fn update_seekable_range(&self) {
+        let imp = self.imp();
+
+        let range = if self.live_channel_id().is_some() {
+            self.query_seekable_range()
+        } else {
+            None
+        };
+        imp.seekable_range.set(range);
+
+        let available = range.is_some();
+        if imp.dvr_available.replace(available) != available {
+            self.notify_dvr_available();
+        }
+        for action in [
+            "player.restart-show",
+            "player.seek-backward",
+            "player.seek-forward",
+            "player.seek-to-live",
+        ] {
+            self.action_set_enabled(action, available);
+        }
+    }
+    /// Asks the pipeline which range of a live stream can be seeked to.
+    fn query_seekable_range(&self) -> Option<(f64, f64)> {
+        // the video filter is part of the pipeline, so its top-level ancestor is the pipeline itself
+        let mut pipeline = self.imp().taginject.get()?.clone().upcast::<gst::Object>();
+        while let Some(parent) = pipeline.parent() {
+            pipeline = parent;
+        }
+        let pipeline = pipeline.downcast::<gst::Element>().ok()?;
+
+        let mut query = gst::query::Seeking::new(gst::Format::Time);
+        if !pipeline.query(&mut query) {
+            return None;
+        }
+
+        match query.result() {
+            (
+                true,
+                gst::GenericFormattedValue::Time(Some(start)),
+                gst::GenericFormattedValue::Time(Some(end)),
+            ) if end.saturating_sub(start).seconds_f64() >= MIN_DVR_WINDOW => {
+                Some((start.seconds_f64(), end.seconds_f64()))
+            }
+            _ => None,
+        }
+    }
+    fn seek_relative(&self, offset: f64) {
+        let Some((start, end)) = self.imp().seekable_range.get() else {
+            return;
+        };
+        let player = self.player();
+
+        player.seek((player.position() + offset).clamp(start, end));
+    }
+    fn seek_to_live(&self) {
+        if let Some((_, end)) = self.imp().seekable_range.get() {
+            self.player().seek(end);
+        }
+    }
+    async fn restart_show(&self) {
+        let Some(channel_id) = self.live_channel_id() else {
+            return;
+        };
+        let show = match TvApplication::get()
+            .current_show(&ChannelId::from(channel_id))
+            .await
+        {
+            Ok(Some(show)) => show,
+            Ok(None) => return,
+            Err(e) => {
+                show_error(e.wrap_err(gettext("Failed to load the current show")));
+                return;
+            }
+        };
+
+        // the end of the seekable range is the live edge, i.e. the current time
+        self.update_seekable_range();
+        let Some((start, end)) = self.imp().seekable_range.get() else {
+            return;
+        };
+        let elapsed = (OffsetDateTime::now_utc() - show.start_time).as_seconds_f64();
+
+        self.player().seek((end - elapsed).clamp(start, end));
+    }
 }
+
+#[derive(Clone, Copy, Debug, Default)]
+enum SleepTimerFade {
+    #[default]
+    Inactive,
+    Fading {
+        /// The volume from before the fade-out.
+        volume: f64,
+        /// The volume that was last set by the fade-out.
+        faded_volume: f64,
+    },
+    /// The user changed the volume during the fade-out.
+    Overridden,
+}
+
 #[derive(Debug)]
 pub enum VideoInfo {
     Live {
@@ -327,3 +1215,126 @@ pub enum VideoInfo {
         channel_id: String,
     },
 }
+
+impl VideoInfo {
+    /// Creates a `VideoInfo::Mediathek` for the user's default quality or the next available one.
+    pub fn mediathek(
+        title: String,
+        subtitle_uri: Option<String>,
+        uri_high: Option<String>,
+        uri_medium: Option<String>,
+        uri_low: Option<String>,
+        channel_id: String,
+    ) -> Self {
+        Self::mediathek_with_default_quality(
+            VideoQuality::for_channel(&channel_id),
+            title,
+            subtitle_uri,
+            uri_high,
+            uri_medium,
+            uri_low,
+            channel_id,
+        )
+    }
+    /// Like [`Self::mediathek`], but with the given default quality instead of the one from the settings.
+    pub fn mediathek_with_default_quality(
+        default_quality: VideoQuality,
+        title: String,
+        subtitle_uri: Option<String>,
+        uri_high: Option<String>,
+        uri_medium: Option<String>,
+        uri_low: Option<String>,
+        channel_id: String,
+    ) -> Self {
+        let preferred_quality = [
+            default_quality,
+            VideoQuality::High,
+            VideoQuality::Medium,
+            VideoQuality::Low,
+        ]
+        .into_iter()
+        .find(|quality| match quality {
+            VideoQuality::High => uri_high.is_some(),
+            VideoQuality::Medium => uri_medium.is_some(),
+            VideoQuality::Low => uri_low.is_some(),
+        })
+        .unwrap_or(default_quality);
+
+        Self::Mediathek {
+            title,
+            preferred_quality,
+            subtitle_uri,
+            uri_high,
+            uri_medium,
+            uri_low,
+            channel_id,
+        }
+    }
+    pub fn title(&self) -> &str {
+        match self {
+            Self::Live { title, .. } | Self::Mediathek { title, .. } => title,
+        }
+    }
+    pub fn channel_id(&self) -> &str {
+        match self {
+            Self::Live { channel_id, .. } | Self::Mediathek { channel_id, .. } => channel_id,
+        }
+    }
+    /// Returns the URI that will be played.
+    pub fn uri(&self) -> Option<&str> {
+        match self {
+            Self::Live { uri, .. } => Some(uri),
+            Self::Mediathek {
+                preferred_quality,
+                uri_high,
+                uri_medium,
+                uri_low,
+                ..
+            } => match preferred_quality {
+                VideoQuality::High => uri_high.as_deref(),
+                VideoQuality::Medium => uri_medium.as_deref(),
+                VideoQuality::Low => uri_low.as_deref(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mediathek_quality_fallback() {
+        // the bandwidth saver prefers the low quality, which isn't available for every show
+        let video = VideoInfo::mediathek_with_default_quality(
+            VideoQuality::Low,
+            "Tagesschau".to_owned(),
+            None,
+            Some("https://example.org/high.mp4".to_owned()),
+            Some("https://example.org/medium.mp4".to_owned()),
+            None,
+            "ARD".to_owned(),
+        );
+
+        assert!(matches!(
+            video,
+            VideoInfo::Mediathek {
+                preferred_quality: VideoQuality::High,
+                ..
+            }
+        ));
+        assert_eq!(video.uri(), Some("https://example.org/high.mp4"));
+
+        let video = VideoInfo::mediathek_with_default_quality(
+            VideoQuality::Low,
+            "Tagesschau".to_owned(),
+            None,
+            None,
+            Some("https://example.org/medium.mp4".to_owned()),
+            Some("https://example.org/low.mp4".to_owned()),
+            "ARD".to_owned(),
+        );
+
+        assert_eq!(video.uri(), Some("https://example.org/low.mp4"));
+    }
+}
@@ -3,11 +3,15 @@
 
 use std::cell::{Cell, RefCell};
 
-use adw::{glib, gtk, prelude::*, subclass::prelude::*};
+use adw::{gio, glib, gtk, prelude::*, subclass::prelude::*};
+use gettextrs::gettext;
 
 use crate::{
+    http, iptv,
     launcher::{ExternalProgramType, ProgramSelector},
-    settings::TvSettings,
+    mediathek::{self, Blocklist, VersionFilter},
+    settings::{TvSettings, VideoQuality},
+    utils::show_error,
 };
 
 use super::live::TvLiveChannelSelector;
@@ -23,6 +27,38 @@ mod imp {
         video_player_row: TemplateChild<adw::ActionRow>,
         #[template_child]
         video_downloader_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        default_quality_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        channel_quality_row: TemplateChild<adw::ExpanderRow>,
+        #[template_child]
+        bandwidth_saver_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        live_previews_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        proxy_mode_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        proxy_url_row: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        iptv_server_local_network_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        iptv_server_proxy_streams_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        custom_channels_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        min_duration_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        audio_description_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        sign_language_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        hide_geo_restricted_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        hide_unavailable_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        blocked_topics_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        blocked_channels_list: TemplateChild<gtk::ListBox>,
 
         #[property(get, set)]
         use_external_player: Cell<bool>,
@@ -65,9 +101,293 @@ mod imp {
         async fn select_live_channels(&self, #[rest] _: &[glib::Value]) {
             self.obj().push_subpage(&TvLiveChannelSelector::new())
         }
+        #[template_callback]
+        async fn import_m3u(&self, #[rest] _: &[glib::Value]) {
+            let filter = gtk::FileFilter::new();
+            filter.set_name(Some(&gettext("M3U Playlists")));
+            filter.add_mime_type("audio/x-mpegurl");
+            filter.add_suffix("m3u");
+            filter.add_suffix("m3u8");
+
+            let dialog = gtk::FileDialog::builder()
+                .title(gettext("Import M3U Playlist"))
+                .filters(&[filter.clone()].into_iter().collect::<gio::ListStore>())
+                .default_filter(&filter)
+                .modal(true)
+                .build();
+
+            let path = match dialog
+                .open_future(self.obj().root().and_downcast_ref::<gtk::Window>())
+                .await
+            {
+                Ok(file) => match file.path() {
+                    Some(path) => path,
+                    None => return,
+                },
+                Err(e) if e.matches(gtk::DialogError::Dismissed) => return,
+                Err(e) => {
+                    show_error(
+                        eyre::Report::msg(e.to_string())
+                            .wrap_err(gettext("Failed to select a file")),
+                    );
+                    return;
+                }
+            };
+
+            match iptv::import_m3u(path).await {
+                Ok(n) => self.obj().add_toast(adw::Toast::new(
+                    // translators: `{}` is replaced by the number of channels
+                    &gettext("Imported {} channels").replace("{}", &n.to_string()),
+                )),
+                Err(e) => show_error(e.wrap_err(gettext("Failed to import playlist"))),
+            }
+        }
+        #[template_callback]
+        fn block_topic(&self, row: &adw::EntryRow) {
+            Blocklist::Topics.add(&row.text());
+            row.set_text("");
+        }
+        #[template_callback]
+        fn block_channel(&self, row: &adw::EntryRow) {
+            Blocklist::Channels.add(&row.text());
+            row.set_text("");
+        }
+        #[template_callback]
+        fn set_proxy_url(&self, row: &adw::EntryRow) {
+            let url = row.text();
+            let url = url.trim();
+
+            // only HTTP proxies are supported
+            if url.is_empty() || http::is_supported_proxy(url) {
+                row.remove_css_class("error");
+                self.settings.set_proxy_url(url);
+            } else {
+                row.add_css_class("error");
+            }
+        }
+        #[template_callback]
+        async fn export_m3u(&self, #[rest] _: &[glib::Value]) {
+            let Some(path) = self
+                .select_export_file(&gettext("Export Live Channels"), "televido.m3u")
+                .await
+            else {
+                return;
+            };
+
+            match iptv::export_m3u(path).await {
+                Ok(()) => self
+                    .obj()
+                    .add_toast(adw::Toast::new(&gettext("Exported live channels"))),
+                Err(e) => show_error(e.wrap_err(gettext("Failed to export live channels"))),
+            }
+        }
+        #[template_callback]
+        async fn export_xmltv(&self, #[rest] _: &[glib::Value]) {
+            let Some(path) = self
+                .select_export_file(&gettext("Export Programme Guide"), "televido.xml")
+                .await
+            else {
+                return;
+            };
+
+            match iptv::export_xmltv(path).await {
+                Ok(()) => self
+                    .obj()
+                    .add_toast(adw::Toast::new(&gettext("Exported programme guide"))),
+                Err(e) => show_error(e.wrap_err(gettext("Failed to export programme guide"))),
+            }
+        }
     }
 
     impl TvPreferencesDialog {
+        async fn select_export_file(
+            &self,
+            title: &str,
+            initial_name: &str,
+        ) -> Option<std::path::PathBuf> {
+            let dialog = gtk::FileDialog::builder()
+                .title(title)
+                .initial_name(initial_name)
+                .modal(true)
+                .build();
+
+            match dialog
+                .save_future(self.obj().root().and_downcast_ref::<gtk::Window>())
+                .await
+            {
+                Ok(file) => file.path(),
+                Err(e) if e.matches(gtk::DialogError::Dismissed) => None,
+                Err(e) => {
+                    show_error(
+                        eyre::Report::msg(e.to_string())
+                            .wrap_err(gettext("Failed to select a file")),
+                    );
+                    None
+                }
+            }
+        }
+        fn update_custom_channels(&self) {
+            self.custom_channels_list.remove_all();
+
+            let custom_channels = self.settings.custom_channels();
+            self.custom_channels_list
+                .set_visible(!custom_channels.is_empty());
+
+            for channel in custom_channels {
+                let row = adw::ActionRow::builder()
+                    .title(&channel.name)
+                    .subtitle(&channel.stream_url)
+                    .subtitle_lines(1)
+                    .build();
+
+                let remove_button = gtk::Button::builder()
+                    .icon_name("user-trash-symbolic")
+                    .tooltip_text(gettext("Remove"))
+                    .valign(gtk::Align::Center)
+                    .css_classes(["flat"])
+                    .build();
+                remove_button.connect_clicked(move |_| iptv::remove_custom_channel(&channel.id));
+                row.add_suffix(&remove_button);
+
+                self.custom_channels_list.append(&row);
+            }
+        }
+        fn setup_playback_quality(&self) {
+            const QUALITIES: [VideoQuality; 3] =
+                [VideoQuality::High, VideoQuality::Medium, VideoQuality::Low];
+
+            let position = |quality| QUALITIES.iter().position(|q| *q == quality);
+
+            self.default_quality_row.set_selected(
+                position(VideoQuality::default_playback()).unwrap_or_default() as u32,
+            );
+            self.default_quality_row.connect_selected_notify(|row| {
+                if let Some(quality) = QUALITIES.get(row.selected() as usize) {
+                    TvSettings::get().set_default_playback_quality(&quality.to_string());
+                }
+            });
+
+            // channels with an override that aren't known anymore can still be reset
+            let mut channels = mediathek::CHANNELS
+                .iter()
+                .map(|channel| channel.to_string())
+                .collect::<Vec<_>>();
+            for channel in self.settings.channel_playback_quality().into_keys() {
+                if !channels.contains(&channel) {
+                    channels.push(channel);
+                }
+            }
+
+            let labels = [
+                gettext("Default"),
+                gettext("High"),
+                gettext("Medium"),
+                gettext("Low"),
+            ];
+            let model = gtk::StringList::new(&labels.each_ref().map(String::as_str));
+            for channel in channels {
+                let row = adw::ComboRow::builder()
+                    .title(&channel)
+                    .model(&model)
+                    .build();
+                row.set_selected(
+                    VideoQuality::channel_override(&channel)
+                        .and_then(position)
+                        .map(|position| position as u32 + 1)
+                        .unwrap_or_default(),
+                );
+                row.connect_selected_notify(move |row| {
+                    let quality = (row.selected() as usize)
+                        .checked_sub(1)
+                        .and_then(|position| QUALITIES.get(position).copied());
+                    VideoQuality::set_channel_override(&channel, quality);
+                });
+
+                self.channel_quality_row.add_row(&row);
+            }
+
+            const BANDWIDTH_SAVER: [&str; 3] = ["auto", "always", "never"];
+            let bandwidth_saver = self.settings.bandwidth_saver();
+            self.bandwidth_saver_row.set_selected(
+                BANDWIDTH_SAVER
+                    .iter()
+                    .position(|value| *value == bandwidth_saver)
+                    .unwrap_or_default() as u32,
+            );
+            self.bandwidth_saver_row.connect_selected_notify(|row| {
+                if let Some(value) = BANDWIDTH_SAVER.get(row.selected() as usize) {
+                    TvSettings::get().set_bandwidth_saver(value);
+                }
+            });
+        }
+        fn setup_proxy(&self) {
+            const PROXY_MODES: [&str; 3] = ["system", "manual", "none"];
+            const MANUAL: u32 = 1;
+
+            let proxy_mode = self.settings.proxy_mode();
+            self.proxy_mode_row.set_selected(
+                PROXY_MODES
+                    .iter()
+                    .position(|value| *value == proxy_mode)
+                    .unwrap_or_default() as u32,
+            );
+            self.proxy_url_row
+                .set_visible(self.proxy_mode_row.selected() == MANUAL);
+            self.proxy_mode_row.connect_selected_notify(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |row| {
+                    if let Some(value) = PROXY_MODES.get(row.selected() as usize) {
+                        slf.settings.set_proxy_mode(value);
+                    }
+                    slf.proxy_url_row.set_visible(row.selected() == MANUAL);
+                }
+            ));
+
+            self.proxy_url_row.set_text(&self.settings.proxy_url());
+        }
+        fn update_blocklist(&self, blocklist: Blocklist) {
+            let list = match blocklist {
+                Blocklist::Topics => &self.blocked_topics_list,
+                Blocklist::Channels => &self.blocked_channels_list,
+            };
+            list.remove_all();
+
+            let entries = blocklist.entries();
+            list.set_visible(!entries.is_empty());
+
+            for entry in entries {
+                let row = adw::ActionRow::builder().title(&entry).build();
+
+                let remove_button = gtk::Button::builder()
+                    .icon_name("user-trash-symbolic")
+                    .tooltip_text(gettext("Remove"))
+                    .valign(gtk::Align::Center)
+                    .css_classes(["flat"])
+                    .build();
+                remove_button.connect_clicked(move |_| blocklist.remove(&entry));
+                row.add_suffix(&remove_button);
+
+                list.append(&row);
+            }
+        }
+        fn setup_version_filter(
+            row: &adw::ComboRow,
+            filter: VersionFilter,
+            set: impl Fn(&TvSettings, &str) + 'static,
+        ) {
+            let position = VersionFilter::ALL
+                .iter()
+                .position(|f| *f == filter)
+                .unwrap_or_default();
+            row.set_selected(position as u32);
+
+            row.connect_selected_notify(move |row| {
+                if let Some(filter) = VersionFilter::ALL.get(row.selected() as usize) {
+                    set(&TvSettings::get(), &filter.to_string());
+                }
+            });
+        }
         fn update_video_player_display_name(&self) {
             let name = self.settings.video_player_name();
             let id = self.settings.video_player_id();
@@ -145,6 +465,67 @@ mod imp {
                     self,
                     move |_| slf.update_video_downloader_display_name()
                 ));
+
+            self.setup_playback_quality();
+            self.settings
+                .bind_live_previews(&*self.live_previews_row, "active")
+                .build();
+            self.setup_proxy();
+            self.settings
+                .bind_iptv_server_local_network(&*self.iptv_server_local_network_row, "active")
+                .build();
+            self.settings
+                .bind_iptv_server_proxy_streams(&*self.iptv_server_proxy_streams_row, "active")
+                .build();
+
+            self.update_custom_channels();
+            self.settings.connect_custom_channels_changed(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |_| slf.update_custom_channels()
+            ));
+
+            self.min_duration_row
+                .set_value(self.settings.min_duration().into());
+            self.min_duration_row.connect_value_notify(|row| {
+                TvSettings::get().set_min_duration(row.value() as u32);
+            });
+            Self::setup_version_filter(
+                &self.audio_description_row,
+                self.settings
+                    .audio_description_filter()
+                    .parse()
+                    .unwrap_or_default(),
+                |settings, value| settings.set_audio_description_filter(value),
+            );
+            Self::setup_version_filter(
+                &self.sign_language_row,
+                self.settings
+                    .sign_language_filter()
+                    .parse()
+                    .unwrap_or_default(),
+                |settings, value| settings.set_sign_language_filter(value),
+            );
+
+            self.settings
+                .bind_hide_geo_restricted(&*self.hide_geo_restricted_row, "active")
+                .build();
+            self.settings
+                .bind_hide_unavailable(&*self.hide_unavailable_row, "active")
+                .build();
+
+            self.update_blocklist(Blocklist::Topics);
+            self.settings.connect_blocked_topics_changed(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |_| slf.update_blocklist(Blocklist::Topics)
+            ));
+            self.update_blocklist(Blocklist::Channels);
+            self.settings.connect_blocked_channels_changed(glib::clone!(
+                #[weak(rename_to = slf)]
+                self,
+                move |_| slf.update_blocklist(Blocklist::Channels)
+            ));
         }
     }
     impl WidgetImpl for TvPreferencesDialog {}
@@ -0,0 +1,223 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Implementation of the `de.k_bo.Televido.RemoteControl1` D-Bus interface.
+//!
+//! The interface is documented in `data/de.k_bo.Televido.RemoteControl1.xml`.
+
+use std::collections::HashMap;
+
+use eyre::WrapErr;
+use zbus::{fdo, interface, object_server::SignalEmitter, zvariant::Value};
+
+use crate::{
+    application::TvApplication,
+    config::APP_ID,
+    deep_link::DeepLink,
+    mediathek::ShowObject,
+    player::VideoInfo,
+    utils::{main_context, spawn, tokio},
+};
+
+#[derive(Debug, Default)]
+struct RemoteControl;
+
+fn object_path() -> String {
+    format!("/{}/RemoteControl", APP_ID.replace('.', "/"))
+}
+
+/// Exports the remote control interface on the session bus.
+pub async fn register() -> eyre::Result<()> {
+    let conn = TvApplication::dbus().await;
+
+    tokio(async move {
+        conn.object_server()
+            .at(object_path().as_str(), RemoteControl)
+            .await?;
+        conn.request_name(format!("{APP_ID}.RemoteControl").as_str())
+            .await?;
+        Ok::<(), zbus::Error>(())
+    })
+    .await
+    .wrap_err("failed to register the remote control interface")
+}
+
+#[interface(name = "de.k_bo.Televido.RemoteControl1")]
+impl RemoteControl {
+    async fn play_live(&self, channel_id: String) -> fdo::Result<()> {
+        main_context(move || async move {
+            let app = TvApplication::get();
+            let Some((channel_id, channel_info)) = app.find_live_channel(&channel_id).await else {
+                return Err(fdo::Error::InvalidArgs(format!(
+                    "unknown live channel `{channel_id}`"
+                )));
+            };
+            // playback isn't awaited because it might wait for the user to choose a player
+            spawn(async move {
+                app.play(VideoInfo::Live {
+                    title: channel_info.name,
+                    uri: channel_info.stream_url,
+                    channel_id: channel_id.to_string(),
+                })
+                .await
+            });
+            Ok(())
+        })
+        .await
+    }
+
+    async fn play_url(&self, url: String, title: String) -> fdo::Result<()> {
+        if url.is_empty() {
+            return Err(fdo::Error::InvalidArgs("empty URL".to_owned()));
+        }
+
+        main_context(move || async move {
+            let video = VideoInfo::mediathek(title, None, None, Some(url), None, String::new());
+            spawn(async move { TvApplication::get().play(video).await });
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn open_link(&self, link: String) -> fdo::Result<()> {
+        DeepLink::parse(&link).map_err(|e| fdo::Error::InvalidArgs(e.to_string()))?;
+
+        main_context(move || async move { TvApplication::get().open_uri(&link) }).await;
+        Ok(())
+    }
+
+    async fn stop(&self) {
+        main_context(|| async { TvApplication::get().stop_playback() }).await
+    }
+
+    async fn list_channels(&self) -> Vec<(String, String, String)> {
+        main_context(|| async {
+            TvApplication::get()
+                .visible_live_channels()
+                .await
+                .into_iter()
+                .map(|(channel_id, channel_info)| {
+                    (
+                        channel_id.to_string(),
+                        channel_info.name,
+                        channel_info.stream_url,
+                    )
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn search(&self, query: String) -> fdo::Result<Vec<HashMap<String, Value<'static>>>> {
+        let client = main_context(|| async { TvApplication::get().mediathek() }).await;
+
+        let result = tokio(async move {
+            client
+                .query_string(&query, false)
+                .size(25)
+                .send()
+                .await
+                .map_err(|e| fdo::Error::Failed(e.to_string()))
+        })
+        .await?;
+
+        Ok(main_context(move || async move {
+            result
+                .results
+                .into_iter()
+                .map(ShowObject::new)
+                .map(search_result)
+                .collect()
+        })
+        .await)
+    }
+
+    #[zbus(signal)]
+    async fn playback_started(
+        emitter: &SignalEmitter<'_>,
+        title: &str,
+        uri: &str,
+        channel_id: &str,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn playback_stopped(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+fn search_result(show: ShowObject) -> HashMap<String, Value<'static>> {
+    let mut result = HashMap::new();
+
+    result.insert("channel".to_owned(), Value::from(show.channel()));
+    result.insert("topic".to_owned(), Value::from(show.topic()));
+    result.insert("title".to_owned(), Value::from(show.title()));
+    result.insert("timestamp".to_owned(), Value::from(show.timestamp()));
+    result.insert("duration".to_owned(), Value::from(show.duration_seconds()));
+
+    for (key, value) in [
+        ("description", show.description()),
+        ("video_url_high", show.video_url_high()),
+        ("video_url_medium", show.video_url_medium()),
+        ("video_url_low", show.video_url_low()),
+        ("subtitle_url", show.subtitle_url()),
+        ("website_url", show.website_url()),
+    ] {
+        if let Some(value) = value {
+            result.insert(key.to_owned(), Value::from(value));
+        }
+    }
+
+    result.insert(
+        "link".to_owned(),
+        Value::from(
+            DeepLink::Play(VideoInfo::mediathek(
+                show.title(),
+                show.subtitle_url(),
+                show.video_url_high(),
+                show.video_url_medium(),
+                show.video_url_low(),
+                show.channel(),
+            ))
+            .to_uri(),
+        ),
+    );
+
+    result
+}
+
+/// Emits the `PlaybackStarted` signal.
+pub fn emit_playback_started(video: &VideoInfo) {
+    let title = video.title().to_owned();
+    let uri = video.uri().unwrap_or_default().to_owned();
+    let channel_id = video.channel_id().to_owned();
+
+    emit(|emitter| async move {
+        RemoteControl::playback_started(&emitter, &title, &uri, &channel_id).await
+    });
+}
+
+/// Emits the `PlaybackStopped` signal.
+pub fn emit_playback_stopped() {
+    emit(|emitter| async move { RemoteControl::playback_stopped(&emitter).await });
+}
+
+fn emit<F, Fut>(f: F)
+where
+    F: FnOnce(SignalEmitter<'static>) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = zbus::Result<()>> + Send,
+{
+    spawn(async move {
+        let conn = TvApplication::dbus().await;
+
+        if let Err(e) = tokio(async move {
+            let iface = conn
+                .object_server()
+                .interface::<_, RemoteControl>(object_path().as_str())
+                .await?;
+            f(iface.signal_emitter().clone()).await
+        })
+        .await
+        {
+            tracing::debug!("failed to emit D-Bus signal: {e:?}");
+        }
+    });
+}
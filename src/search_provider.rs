@@ -0,0 +1,212 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Implementation of the `org.gnome.Shell.SearchProvider2` D-Bus interface.
+//!
+//! Result identifiers are `televido://` links, so activating a result only has to open the link.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use eyre::WrapErr;
+use gettextrs::gettext;
+use zbus::{interface, zvariant::Value};
+
+use crate::{
+    application::TvApplication,
+    channel_icons::export_channel_icon,
+    config::APP_ID,
+    deep_link::DeepLink,
+    iptv::{channel_logo_dir, live_playlist_entries},
+    mediathek::ShowObject,
+    player::VideoInfo,
+    utils::{main_context, tokio},
+};
+
+#[derive(Clone, Debug)]
+struct ResultMeta {
+    name: String,
+    description: String,
+    gicon: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct SearchProvider {
+    results: Arc<Mutex<HashMap<String, ResultMeta>>>,
+}
+
+/// Exports the search provider on the session bus.
+pub async fn register() -> eyre::Result<()> {
+    let conn = TvApplication::dbus().await;
+
+    tokio(async move {
+        conn.object_server()
+            .at(
+                format!("/{}/SearchProvider", APP_ID.replace('.', "/")).as_str(),
+                SearchProvider::default(),
+            )
+            .await?;
+        conn.request_name(format!("{APP_ID}.SearchProvider").as_str())
+            .await?;
+        Ok::<(), zbus::Error>(())
+    })
+    .await
+    .wrap_err("failed to register the search provider")
+}
+
+#[interface(name = "org.gnome.Shell.SearchProvider2")]
+impl SearchProvider {
+    async fn get_initial_result_set(&self, terms: Vec<String>) -> Vec<String> {
+        self.search(terms).await
+    }
+
+    async fn get_subsearch_result_set(
+        &self,
+        _previous_results: Vec<String>,
+        terms: Vec<String>,
+    ) -> Vec<String> {
+        self.search(terms).await
+    }
+
+    fn get_result_metas(&self, identifiers: Vec<String>) -> Vec<HashMap<String, Value<'static>>> {
+        let results = self.results.lock().unwrap();
+
+        identifiers
+            .into_iter()
+            .filter_map(|id| {
+                let meta = results.get(&id)?;
+
+                let mut map = HashMap::new();
+                map.insert("id".to_owned(), Value::from(id));
+                map.insert("name".to_owned(), Value::from(meta.name.clone()));
+                map.insert(
+                    "description".to_owned(),
+                    Value::from(meta.description.clone()),
+                );
+                if let Some(gicon) = &meta.gicon {
+                    map.insert("gicon".to_owned(), Value::from(gicon.clone()));
+                }
+                Some(map)
+            })
+            .collect()
+    }
+
+    async fn activate_result(&self, identifier: String, _terms: Vec<String>, _timestamp: u32) {
+        main_context(move || async move { TvApplication::get().open_uri(&identifier) }).await
+    }
+
+    async fn launch_search(&self, terms: Vec<String>, _timestamp: u32) {
+        let uri = DeepLink::Search {
+            query_string: terms.join(" "),
+        }
+        .to_uri();
+
+        main_context(move || async move { TvApplication::get().open_uri(&uri) }).await
+    }
+}
+
+impl SearchProvider {
+    async fn search(&self, terms: Vec<String>) -> Vec<String> {
+        let results = main_context(move || async move {
+            // the application would otherwise quit if it was only started for the search provider
+            let _hold_guard = TvApplication::get().hold();
+
+            let mut results = search_live_channels(&terms).await;
+            match search_mediathek(&terms).await {
+                Ok(mediathek_results) => results.extend(mediathek_results),
+                Err(e) => tracing::warn!("{e:?}"),
+            }
+            results
+        })
+        .await;
+
+        let ids = results.iter().map(|(id, _)| id.clone()).collect();
+        // only the metadata of the latest results is requested
+        *self.results.lock().unwrap() = results.into_iter().collect();
+        ids
+    }
+}
+
+async fn search_live_channels(terms: &[String]) -> Vec<(String, ResultMeta)> {
+    let terms = terms.iter().map(|t| t.to_lowercase()).collect::<Vec<_>>();
+
+    let entries = match live_playlist_entries().await {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("{e:?}");
+            return Vec::new();
+        }
+    };
+
+    entries
+        .into_iter()
+        .filter(|entry| {
+            let name = entry.tvg_name.to_lowercase();
+            let id = entry.tvg_id.to_lowercase();
+            terms
+                .iter()
+                .all(|term| name.contains(term) || id.contains(term))
+        })
+        .map(|entry| {
+            (
+                DeepLink::Live {
+                    channel_id: entry.tvg_id,
+                }
+                .to_uri(),
+                ResultMeta {
+                    name: entry.tvg_name,
+                    description: gettext("Live"),
+                    gicon: entry.tvg_logo,
+                },
+            )
+        })
+        .collect()
+}
+
+async fn search_mediathek(terms: &[String]) -> eyre::Result<Vec<(String, ResultMeta)>> {
+    let client = TvApplication::get().mediathek();
+    let query_string = terms.join(" ");
+
+    let result = tokio(async move {
+        client
+            .query_string(&query_string, false)
+            .size(5)
+            .send()
+            .await
+    })
+    .await?;
+
+    let logo_dir = channel_logo_dir();
+    let mut results = Vec::new();
+
+    for show in result.results.into_iter().map(ShowObject::new) {
+        let gicon = export_channel_icon(&show.channel(), &logo_dir)
+            .await
+            .ok()
+            .flatten()
+            .map(|path| path.to_string_lossy().into_owned());
+
+        let uri = DeepLink::Play(VideoInfo::mediathek(
+            show.title(),
+            show.subtitle_url(),
+            show.video_url_high(),
+            show.video_url_medium(),
+            show.video_url_low(),
+            show.channel(),
+        ))
+        .to_uri();
+
+        results.push((
+            uri,
+            ResultMeta {
+                name: show.title(),
+                description: format!("{} · {}", show.channel(), show.topic()),
+                gicon,
+            },
+        ));
+    }
+
+    Ok(results)
+}
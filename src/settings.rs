@@ -1,12 +1,12 @@
 // SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{cell::OnceCell, fmt::Display, str::FromStr};
+use std::{cell::OnceCell, collections::HashMap, fmt::Display, str::FromStr};
 
 use adw::{gio, glib, prelude::*};
 use gsettings_macro::gen_settings;
 
-use crate::{config::BASE_APP_ID, zapp::ChannelId};
+use crate::{config::BASE_APP_ID, iptv::CustomChannel, network, zapp::ChannelId};
 
 #[gen_settings(file = "data/de.k_bo.Televido.gschema.xml")]
 #[gen_settings_define(
@@ -14,6 +14,21 @@ use crate::{config::BASE_APP_ID, zapp::ChannelId};
     arg_type = "Vec<ChannelId>",
     ret_type = "Vec<ChannelId>"
 )]
+#[gen_settings_define(
+    key_name = "custom-channels",
+    arg_type = "Vec<CustomChannel>",
+    ret_type = "Vec<CustomChannel>"
+)]
+#[gen_settings_define(
+    key_name = "channel-playback-quality",
+    arg_type = "HashMap<String, String>",
+    ret_type = "HashMap<String, String>"
+)]
+#[gen_settings_define(
+    key_name = "live-stream-variants",
+    arg_type = "HashMap<String, String>",
+    ret_type = "HashMap<String, String>"
+)]
 pub struct TvSettings;
 
 impl TvSettings {
@@ -91,6 +106,32 @@ impl VideoQuality {
             .parse()
             .unwrap()
     }
+    /// The preferred quality for a Mediathek channel, e.g. `ARTE.DE`.
+    ///
+    /// This is always [`VideoQuality::Low`] while the bandwidth saver is active.
+    pub fn for_channel(channel: &str) -> Self {
+        if network::bandwidth_saver() {
+            return Self::Low;
+        }
+        Self::channel_override(channel).unwrap_or_else(Self::default_playback)
+    }
+    /// The quality that overrides the default quality for a Mediathek channel.
+    pub fn channel_override(channel: &str) -> Option<Self> {
+        TvSettings::get()
+            .channel_playback_quality()
+            .get(channel)
+            .and_then(|quality| quality.parse().ok())
+    }
+    pub fn set_channel_override(channel: &str, quality: Option<Self>) {
+        let settings = TvSettings::get();
+        let mut qualities = settings.channel_playback_quality();
+
+        match quality {
+            Some(quality) => qualities.insert(channel.to_owned(), quality.to_string()),
+            None => qualities.remove(channel),
+        };
+        settings.set_channel_playback_quality(qualities);
+    }
 }
 impl FromStr for VideoQuality {
     type Err = eyre::Report;
@@ -0,0 +1,84 @@
+// SPDX-FileCopyrightText: David Cabot <d-k-bo@mailbox.org>
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Still frames of videos and live streams, taken with GStreamer's `playbin`.
+
+use std::{rc::Rc, time::Duration};
+
+use adw::glib;
+use eyre::{eyre, OptionExt};
+use futures_util::{lock::Mutex, Stream, StreamExt};
+use gst::prelude::*;
+
+/// The frame is taken at this fraction of the video to skip intros and black frames.
+const FRAME_POSITION_PERCENT: u64 = 10;
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+thread_local! {
+    /// Only one video is opened at a time, so a list of results doesn't start dozens of pipelines.
+    static LOCK: Rc<Mutex<()>> = Rc::new(Mutex::new(()));
+}
+
+/// Takes a frame from a video and encodes it as PNG with the given width.
+///
+/// For videos with a known duration, the frame is taken a bit after the start.
+pub async fn take(uri: &str, width: i32) -> eyre::Result<glib::Bytes> {
+    let lock = LOCK.with(Rc::clone);
+    let _guard = lock.lock().await;
+
+    let fakesink = || gst::ElementFactory::make("fakesink").build();
+    let playbin = gst::ElementFactory::make("playbin")
+        .property("uri", uri)
+        .property("audio-sink", fakesink()?)
+        .property("video-sink", fakesink()?)
+        .build()?;
+    let mut messages = playbin.bus().ok_or_eyre("playbin has no bus")?.stream();
+
+    let frame = glib::future_with_timeout(TIMEOUT, async {
+        // live sources only produce frames while playing
+        if playbin.set_state(gst::State::Paused)? == gst::StateChangeSuccess::NoPreroll {
+            playbin.set_state(gst::State::Playing)?;
+        }
+        wait_for_preroll(&mut messages).await?;
+
+        // live streams don't have a duration
+        if let Some(duration) = playbin.query_duration::<gst::ClockTime>() {
+            playbin.seek_simple(
+                gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT,
+                duration * FRAME_POSITION_PERCENT / 100,
+            )?;
+            wait_for_preroll(&mut messages).await?;
+        }
+
+        let caps = gst::Caps::builder("image/png")
+            .field("width", width)
+            .field("pixel-aspect-ratio", gst::Fraction::new(1, 1))
+            .build();
+        let sample = playbin
+            .emit_by_name::<Option<gst::Sample>>("convert-sample", &[&caps])
+            .ok_or_eyre("failed to convert the frame")?;
+        let buffer = sample.buffer().ok_or_eyre("the frame is empty")?;
+        let map = buffer.map_readable()?;
+
+        Ok::<_, eyre::Report>(glib::Bytes::from(map.as_slice()))
+    })
+    .await;
+
+    let _ = playbin.set_state(gst::State::Null);
+
+    frame.map_err(|_| eyre!("timed out while taking a frame from the video"))?
+}
+
+async fn wait_for_preroll(
+    messages: &mut (impl Stream<Item = gst::Message> + Unpin),
+) -> eyre::Result<()> {
+    while let Some(message) = messages.next().await {
+        match message.view() {
+            gst::MessageView::AsyncDone(_) => return Ok(()),
+            gst::MessageView::Error(e) => return Err(e.error().into()),
+            _ => (),
+        }
+    }
+
+    Err(eyre!("the pipeline stopped unexpectedly"))
+}
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    cell::{OnceCell, RefCell},
+    cell::{Cell, OnceCell, RefCell},
     fmt::Debug,
     future::Future,
     pin::Pin,
@@ -33,6 +33,19 @@ where
         .expect("tokio thread panicked")
 }
 
+/// Runs a future on the GLib main context, e.g. to access GObjects from a tokio task.
+pub async fn main_context<F, Fut, T>(f: F) -> T
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = T> + 'static,
+    T: Send + 'static,
+{
+    glib::MainContext::default()
+        .spawn_from_within(f)
+        .await
+        .expect("main context task panicked")
+}
+
 pub fn spawn<Fut>(fut: Fut)
 where
     Fut: Future<Output = ()> + 'static,
@@ -89,6 +102,12 @@ pub fn format_duration(duration: &Duration) -> String {
     }
 }
 
+/// Whether `host` is `domain` or one of its subdomains.
+pub fn is_same_domain(host: &str, domain: &str) -> bool {
+    host.strip_suffix(domain)
+        .is_some_and(|subdomain| subdomain.is_empty() || subdomain.ends_with('.'))
+}
+
 pub fn show_error(e: eyre::Report) {
     if let Some(window) = TvApplication::get()
         .active_window()
@@ -136,6 +155,59 @@ impl ListStoreExtManual for gio::ListStore {
     }
 }
 
+/// Limits how many tasks run at the same time on the main thread.
+#[derive(Clone, Debug)]
+pub struct TaskLimiter {
+    inner: Rc<TaskLimiterInner>,
+}
+
+#[derive(Debug)]
+struct TaskLimiterInner {
+    max_running: usize,
+    running: Cell<usize>,
+    waiting: RefCell<Vec<Waker>>,
+}
+
+impl TaskLimiter {
+    pub fn new(max_running: usize) -> Self {
+        Self {
+            inner: Rc::new(TaskLimiterInner {
+                max_running,
+                running: Cell::new(0),
+                waiting: Default::default(),
+            }),
+        }
+    }
+    /// Waits until fewer than `max_running` tasks are running and then runs `fut`.
+    pub async fn run<T>(&self, fut: impl Future<Output = T>) -> T {
+        std::future::poll_fn(|cx| {
+            let inner = &self.inner;
+            if inner.running.get() < inner.max_running {
+                inner.running.set(inner.running.get() + 1);
+                Poll::Ready(())
+            } else {
+                inner.waiting.borrow_mut().push(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await;
+
+        struct Guard<'a>(&'a TaskLimiterInner);
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                self.0.running.set(self.0.running.get() - 1);
+                // all waiting tasks are woken because some of them might have been dropped in the meantime
+                for waker in self.0.waiting.take() {
+                    waker.wake();
+                }
+            }
+        }
+        let _guard = Guard(&self.inner);
+
+        fut.await
+    }
+}
+
 mod async_resource {
     use super::*;
 
@@ -153,6 +225,8 @@ mod async_resource {
     #[derive(Default)]
     struct AsyncResourceInner<T: Clone + Debug> {
         data: RefCell<Option<T>>,
+        /// Incremented by every load, so the results of outdated loads are discarded.
+        generation: Cell<u64>,
         load_fn: OnceCell<LoadFn<T>>,
         wakers: RefCell<Vec<Waker>>,
     }
@@ -172,13 +246,22 @@ mod async_resource {
     }
 
     impl<T: 'static + Clone + Debug> AsyncResource<T> {
+        /// Starts loading the data again.
+        ///
+        /// The previous data is discarded immediately, so awaiting the resource afterwards yields the new data.
         pub fn load(&self) {
             match self.inner.load_fn.get().cloned() {
                 Some(load_fn) => {
+                    let generation = self.inner.generation.get() + 1;
+                    self.inner.generation.set(generation);
+                    *self.inner.data.borrow_mut() = None;
+
                     let inner = self.inner.clone();
                     spawn(async move {
-                        *inner.data.borrow_mut() = None;
                         let data = load_fn().await;
+                        if inner.generation.get() != generation {
+                            return;
+                        }
                         *inner.data.borrow_mut() = Some(data);
                         for waker in inner.wakers.borrow_mut().drain(..) {
                             waker.wake();
@@ -188,6 +271,11 @@ mod async_resource {
                 None => panic!("Resource load function has not been initialized"),
             }
         }
+        /// Loads the data again and waits for the result.
+        pub async fn reload(&self) -> T {
+            self.load();
+            self.clone().await
+        }
     }
 
     impl<T: Clone + Debug> Future for AsyncResource<T> {
@@ -206,4 +294,96 @@ mod async_resource {
             }
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use futures_util::FutureExt;
+
+        use super::*;
+
+        /// A load that only finishes when the test completes it.
+        #[derive(Default)]
+        struct PendingLoad {
+            result: Cell<Option<Option<u32>>>,
+            waker: RefCell<Option<Waker>>,
+        }
+
+        impl PendingLoad {
+            fn complete(&self, result: Option<u32>) {
+                self.result.set(Some(result));
+                if let Some(waker) = self.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+
+        /// Lets the spawned tasks run.
+        async fn run_pending_tasks() {
+            glib::timeout_future(Duration::ZERO).await
+        }
+
+        /// Simulates the live channels, which fail to load while offline and are reloaded when back online.
+        ///
+        /// This is a single test because the tasks run on the default main context, which can only be
+        /// acquired by one thread at a time.
+        #[test]
+        fn reload_after_reconnect() {
+            glib::MainContext::default().block_on(async {
+                let loads = Rc::new(RefCell::new(Vec::<Rc<PendingLoad>>::new()));
+                let resource = AsyncResource::<Option<u32>>::default();
+                resource.set_load_fn({
+                    let loads = loads.clone();
+                    move || {
+                        let load = Rc::new(PendingLoad::default());
+                        loads.borrow_mut().push(load.clone());
+                        Box::pin(std::future::poll_fn(move |cx| match load.result.take() {
+                            Some(result) => Poll::Ready(result),
+                            None => {
+                                load.waker.replace(Some(cx.waker().clone()));
+                                Poll::Pending
+                            }
+                        }))
+                    }
+                });
+                let load = |index: usize| loads.borrow()[index].clone();
+
+                // offline
+                resource.load();
+                run_pending_tasks().await;
+                load(0).complete(None);
+                assert_eq!(resource.clone().await, None);
+
+                // back online
+                let mut reloaded = std::pin::pin!(resource.reload());
+                assert_eq!(reloaded.as_mut().now_or_never(), None);
+                run_pending_tasks().await;
+                load(1).complete(Some(42));
+                assert_eq!(reloaded.await, Some(42));
+
+                // an outdated load finishes before the newer one
+                resource.load();
+                run_pending_tasks().await;
+                resource.load();
+                run_pending_tasks().await;
+                load(2).complete(None);
+                run_pending_tasks().await;
+                assert_eq!(resource.clone().now_or_never(), None);
+                load(3).complete(Some(1));
+                assert_eq!(resource.clone().await, Some(1));
+
+                // an outdated load finishes after the newer one
+                resource.load();
+                run_pending_tasks().await;
+                resource.load();
+                run_pending_tasks().await;
+                load(5).complete(Some(2));
+                assert_eq!(resource.clone().await, Some(2));
+                load(4).complete(None);
+                run_pending_tasks().await;
+                assert_eq!(resource.clone().await, Some(2));
+
+                assert_eq!(loads.borrow().len(), 6);
+            });
+        }
+    }
 }
@@ -5,7 +5,7 @@ use adw::{gio, glib, gtk, prelude::*, subclass::prelude::*};
 
 use crate::{
     application::TvApplication, config::PROFILE, live::TvLiveView, mediathek::TvMediathekView,
-    settings::TvSettings,
+    network, settings::TvSettings,
 };
 
 mod imp {
@@ -14,14 +14,16 @@ mod imp {
     #[derive(Debug, Default, gtk::CompositeTemplate)]
     #[template(file = "src/window.blp")]
     pub struct TvWindow {
+        #[template_child]
+        offline_banner: TemplateChild<adw::Banner>,
         #[template_child]
         pub(super) toast_overlay: TemplateChild<adw::ToastOverlay>,
         #[template_child]
-        stack: TemplateChild<adw::ViewStack>,
+        pub(super) stack: TemplateChild<adw::ViewStack>,
         #[template_child]
         live_view: TemplateChild<TvLiveView>,
         #[template_child]
-        mediathek_view: TemplateChild<TvMediathekView>,
+        pub(super) mediathek_view: TemplateChild<TvMediathekView>,
     }
 
     #[glib::object_subclass]
@@ -81,6 +83,13 @@ mod imp {
                 .bind_visible_view(&*self.stack, "visible-child-name")
                 .flags(gio::SettingsBindFlags::DEFAULT)
                 .build();
+
+            self.offline_banner.set_revealed(!network::is_online());
+            network::connect_online_changed(glib::clone!(
+                #[weak(rename_to = offline_banner)]
+                self.offline_banner,
+                move |online| offline_banner.set_revealed(!online)
+            ));
         }
     }
     impl WidgetImpl for TvWindow {}
@@ -107,6 +116,11 @@ impl TvWindow {
 
         win
     }
+    pub fn search_mediathek(&self, query_string: &str) {
+        let imp = self.imp();
+        imp.stack.set_visible_child_name("mediathek");
+        imp.mediathek_view.set_query_string(query_string);
+    }
     pub fn add_toast(&self, toast: adw::Toast) {
         self.imp().toast_overlay.add_toast(toast)
     }
@@ -23,46 +23,50 @@
 
 use adw::glib;
 use indexmap::IndexMap;
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
 use time::OffsetDateTime;
 
-use crate::config::{APP_ID, PROJECT_URL, VERSION};
+use crate::{
+    error::{retry, ApiError},
+    http,
+};
 
 const ZAPP_BACKEND_URL: &str = "https://api.zapp.mediathekview.de";
 
-#[derive(Debug)]
-pub struct Zapp {
-    http: reqwest::Client,
-}
+#[derive(Debug, Default)]
+pub struct Zapp {}
 impl Zapp {
-    pub fn new() -> eyre::Result<Self> {
-        Ok(Self {
-            http: reqwest::Client::builder()
-                .user_agent(format!("{APP_ID}/{VERSION} ({PROJECT_URL})"))
-                .build()?,
-        })
+    pub async fn channel_info_list(&self) -> Result<ChannelInfoList, ApiError> {
+        self.get(format!("{ZAPP_BACKEND_URL}/v1/channelInfoList"))
+            .await
     }
-}
-impl Zapp {
-    pub async fn channel_info_list(&self) -> eyre::Result<ChannelInfoList> {
-        Ok(self
-            .http
-            .get(format!("{ZAPP_BACKEND_URL}/v1/channelInfoList"))
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?)
+    pub async fn shows(&self, channel_id: &ChannelId) -> Result<ShowsResult, ApiError> {
+        self.get(format!("{ZAPP_BACKEND_URL}/v1/shows/{channel_id}"))
+            .await
     }
-    pub async fn shows(&self, channel_id: &ChannelId) -> eyre::Result<ShowsResult> {
-        Ok(self
-            .http
-            .get(format!("{ZAPP_BACKEND_URL}/v1/shows/{channel_id}"))
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?)
+    pub async fn current_show(&self, channel_id: &ChannelId) -> Result<Option<Show>, ApiError> {
+        let now = OffsetDateTime::now_utc();
+
+        Ok(match self.shows(channel_id).await? {
+            ShowsResult::Shows(shows) => shows
+                .into_iter()
+                .find(|show| show.start_time <= now && now < show.end_time),
+            ShowsResult::Error(_) => None,
+        })
+    }
+    async fn get<T: DeserializeOwned>(&self, url: String) -> Result<T, ApiError> {
+        let (http, url) = (&http::client(), &url);
+
+        retry(move || async move {
+            Ok(http
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?)
+        })
+        .await
     }
 }
 